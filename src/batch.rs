@@ -0,0 +1,123 @@
+use crate::classifier::Classifier;
+use crate::decoder::PartType;
+use crate::frame::{self, FrameFormat};
+use crate::particle_extractor::{self, ExtractConfig};
+use crate::preprocess;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Aggregate statistics over every frame in a run
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunSummary {
+    /// Frames that were loaded and processed
+    pub frames: usize,
+    /// Files that could not be parsed, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+    pub counts: HashMap<PartType, usize>,
+    /// Energy of all extracted particles
+    pub total_energy: f64,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frames processed: {}", self.frames)?;
+        writeln!(f, "files skipped:    {}", self.skipped.len())?;
+        for (path, reason) in &self.skipped {
+            writeln!(f, "  {}: {reason}", path.display())?;
+        }
+        for ty in PartType::ALL {
+            writeln!(
+                f,
+                "{:<8} {:>10}",
+                format!("{ty:?}"),
+                self.counts.get(&ty).copied().unwrap_or(0)
+            )?;
+        }
+        write!(f, "total energy:     {:.2}", self.total_energy)
+    }
+}
+
+/// Loads, extracts and classifies every file in `dir` (in name order), skipping files that
+/// fail to parse instead of aborting. Only fails if the directory itself can't be read.
+pub fn process_directory<P>(
+    dir: P,
+    extract: &ExtractConfig,
+    classifier: &dyn Classifier,
+) -> io::Result<RunSummary>
+where
+    P: AsRef<Path>,
+{
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut summary = RunSummary::default();
+
+    for path in paths {
+        let grid = match frame::read_frame(&path, FrameFormat::from_path(&path)) {
+            Ok(grid) if !grid.is_empty() => preprocess::prepare(grid, extract),
+            Ok(_) => {
+                summary.skipped.push((path, "empty frame".to_string()));
+                continue;
+            }
+            Err(e) => {
+                summary.skipped.push((path, e.to_string()));
+                continue;
+            }
+        };
+
+        let particles = match particle_extractor::extract_classified(&grid, extract, classifier) {
+            Ok(particles) => particles,
+            Err(e) => {
+                summary.skipped.push((path, e.to_string()));
+                continue;
+            }
+        };
+        let types = classifier.classify_all(&particles, &grid);
+        for (particle, part_type) in particles.iter().zip(types) {
+            *summary.counts.entry(part_type).or_default() += 1;
+            summary.total_energy += particle.total_energy(&grid) as f64;
+        }
+        summary.frames += 1;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::HeuristicClassifier;
+
+    #[test]
+    fn test_process_directory() {
+        let dir = std::env::temp_dir().join(format!("muon_decoder_batch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "0 0 0\n0 5 0\n0 0 0\n").unwrap();
+        std::fs::write(dir.join("b.csv"), "1,0,0\n0,0,0\n0,0,2\n").unwrap();
+        std::fs::write(dir.join("c.txt"), "0 x 0\n").unwrap();
+
+        let summary = process_directory(
+            &dir,
+            &ExtractConfig::default(),
+            &HeuristicClassifier::default(),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(summary.frames, 2);
+        assert_eq!(summary.skipped.len(), 1);
+        assert!(summary.skipped[0].0.ends_with("c.txt"));
+        assert_eq!(summary.counts.get(&PartType::GAMMA), Some(&3));
+        assert_eq!(summary.total_energy, 8.0);
+
+        let report = summary.to_string();
+        let line = report.lines().find(|line| line.contains("c.txt")).unwrap();
+        assert!(line.ends_with(&summary.skipped[0].1));
+    }
+}
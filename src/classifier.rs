@@ -0,0 +1,98 @@
+use crate::decoder::{ClassifierConfig, PartType, Particle};
+
+/// Decides the type of an extracted particle.
+///
+/// The viewer, batch runs and exports all go through this trait, so custom logic (a trained
+/// model, say) can replace the built-in rules without touching them. Implementations should
+/// return the [`Particle::fixed_type`] of particles that have one.
+pub trait Classifier {
+    fn classify(&self, particle: &Particle, grid: &[Vec<f32>]) -> PartType;
+
+    /// [`Classifier::classify`] of every particle, in order. Classifiers that can share
+    /// work or threads between particles override it; the default takes them one by one.
+    fn classify_all(&self, particles: &[Particle], grid: &[Vec<f32>]) -> Vec<PartType> {
+        particles.iter().map(|p| self.classify(p, grid)).collect()
+    }
+
+    /// Thresholds of the built-in heuristics this classifier follows, if any, so features
+    /// shown next to its types (a Bragg peak, say) are judged the same way
+    fn config(&self) -> Option<&ClassifierConfig> {
+        None
+    }
+
+    /// How sure the classifier is of [`Classifier::classify`]'s answer, from 0 to 1.
+    /// Classifiers without a notion of confidence are always sure.
+    fn confidence(&self, _particle: &Particle, _grid: &[Vec<f32>]) -> f32 {
+        1.0
+    }
+}
+
+/// The rule-based classification of [`Particle::particle_type_with`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeuristicClassifier {
+    pub config: ClassifierConfig,
+}
+
+impl Classifier for HeuristicClassifier {
+    fn classify(&self, particle: &Particle, grid: &[Vec<f32>]) -> PartType {
+        particle.particle_type_with(grid, &self.config)
+    }
+
+    /// Runs [`crate::decoder::classify_all`], on the thread pool with the `rayon` feature
+    fn classify_all(&self, particles: &[Particle], grid: &[Vec<f32>]) -> Vec<PartType> {
+        crate::decoder::classify_all(particles, grid, &self.config)
+    }
+
+    fn confidence(&self, particle: &Particle, grid: &[Vec<f32>]) -> f32 {
+        particle.confidence_with(grid, &self.config)
+    }
+
+    fn config(&self) -> Option<&ClassifierConfig> {
+        Some(&self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Labels tracks by size alone
+    struct SizeClassifier {
+        min_muon_size: usize,
+    }
+
+    impl Classifier for SizeClassifier {
+        fn classify(&self, particle: &Particle, _grid: &[Vec<f32>]) -> PartType {
+            if particle.size() >= self.min_muon_size {
+                PartType::MUON
+            } else {
+                PartType::GAMMA
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_classifier() {
+        let grid = vec![vec![20.0f32; 16]; 16];
+        let line = Particle::new((0..10).map(|x| (x, 3)).collect());
+        let dot = Particle::new(vec![(5, 5)]);
+
+        let classifier: Box<dyn Classifier> = Box::new(SizeClassifier { min_muon_size: 5 });
+        assert_eq!(classifier.classify(&line, &grid), PartType::MUON);
+        assert_eq!(classifier.classify(&dot, &grid), PartType::GAMMA);
+        assert_eq!(classifier.confidence(&line, &grid), 1.0);
+
+        let both = [line.clone(), dot.clone()];
+        assert_eq!(
+            classifier.classify_all(&both, &grid),
+            vec![PartType::MUON, PartType::GAMMA]
+        );
+
+        let heuristic: Box<dyn Classifier> = Box::new(HeuristicClassifier::default());
+        assert_eq!(heuristic.classify(&line, &grid), line.particle_type(&grid));
+        assert_eq!(
+            heuristic.classify_all(&both, &grid),
+            vec![line.particle_type(&grid), dot.particle_type(&grid)]
+        );
+    }
+}
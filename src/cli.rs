@@ -0,0 +1,126 @@
+use muon_decoder::particle_extractor::ExtractConfig;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: muon_decoder [FRAME] [options]
+
+options:
+  --range <N>        neighbour search range for extraction (default 1)
+  --threshold <F>    energy at or below which cells are background (default 0)
+  --gamma-radius <N> merge GAMMA hits within N pixels of each other (default 0, off)
+  --denoise <KIND>   filter the frame before extraction: median or gaussian
+  --gain <F>         calibration gain, physical units per ADU (default 1)
+  --offset <F>       calibration offset added to every hit (default 0)
+  --saturation <F>   value the detector saturates at, counted and flagged (default off)
+  --headless         print particle counts per type instead of opening the viewer
+  --report <PATH>    also write frame stats and particle features as JSON (implies --headless)
+  --batch <DIR>      process every frame in DIR and print a run summary
+  -h, --help         show this message";
+
+/// Parsed command line
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub input: Option<PathBuf>,
+    pub extract: ExtractConfig,
+    /// Pixel value the detector saturates at
+    pub saturation: Option<f32>,
+    pub headless: bool,
+    /// Where to write the JSON report of the frame
+    pub report: Option<PathBuf>,
+    pub batch: Option<PathBuf>,
+    pub help: bool,
+}
+
+/// Parses the arguments following the program name
+pub fn parse<I>(args: I) -> Result<Args, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--range" => parsed.extract.range = value(&arg, args.next())?,
+            "--threshold" => parsed.extract.threshold = value(&arg, args.next())?,
+            "--gamma-radius" => parsed.extract.gamma_merge_radius = value(&arg, args.next())?,
+            "--denoise" => parsed.extract.denoise = Some(value(&arg, args.next())?),
+            "--gain" => parsed.extract.calibration.gain = value(&arg, args.next())?,
+            "--offset" => parsed.extract.calibration.offset = value(&arg, args.next())?,
+            "--saturation" => parsed.saturation = Some(value(&arg, args.next())?),
+            "--headless" => parsed.headless = true,
+            "--report" => {
+                parsed.report = Some(value(&arg, args.next())?);
+                parsed.headless = true;
+            }
+            "--batch" => parsed.batch = Some(value(&arg, args.next())?),
+            "-h" | "--help" => parsed.help = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{flag}`")),
+            _ if parsed.input.is_some() => return Err(format!("unexpected argument `{arg}`")),
+            _ => parsed.input = Some(PathBuf::from(arg)),
+        }
+    }
+
+    if parsed.headless && parsed.input.is_none() {
+        return Err("--headless and --report need a frame to process".to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// Parses the value following `flag`
+fn value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("`{flag}` needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{flag}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = args("frame.txt --range 2 --threshold 0.5 --headless").unwrap();
+
+        assert_eq!(parsed.input, Some(PathBuf::from("frame.txt")));
+        assert_eq!(parsed.extract.range, 2);
+        assert_eq!(parsed.extract.threshold, 0.5);
+        assert_eq!(
+            args("--gamma-radius 2").unwrap().extract.gamma_merge_radius,
+            2
+        );
+        assert_eq!(
+            args("--denoise median").unwrap().extract.denoise,
+            Some(muon_decoder::preprocess::Denoise::Median)
+        );
+        let calibrated = args("--gain 2.5 --offset -1").unwrap().extract.calibration;
+        assert_eq!((calibrated.gain, calibrated.offset), (2.5, -1.0));
+        assert_eq!(args("--saturation 4095").unwrap().saturation, Some(4095.0));
+        assert!(parsed.headless);
+        let report = args("frame.txt --report out.json").unwrap();
+        assert_eq!(report.report, Some(PathBuf::from("out.json")));
+        assert!(report.headless);
+        assert_eq!(args("").unwrap(), Args::default());
+        assert_eq!(
+            args("--batch runs").unwrap().batch,
+            Some(PathBuf::from("runs"))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(args("--range").is_err());
+        assert!(args("--range two").is_err());
+        assert!(args("--denoise blur").is_err());
+        assert!(args("--bogus").is_err());
+        assert!(args("a.txt b.txt").is_err());
+        assert!(args("--headless").is_err());
+        assert!(args("--report out.json").is_err());
+    }
+}
@@ -1,8 +1,10 @@
-use geo::{Area, ConvexHull, Euclidean, Length};
-use geo_types::{Coord, MultiPoint};
+use geo::{Area, ConvexHull, Euclidean, Length, Simplify};
+use geo_types::{Coord, LineString, MultiPoint};
 use std::f64::consts::PI;
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub enum PartType {
     ALPHA,
@@ -11,125 +13,1047 @@ pub enum PartType {
     MUON,
     UNKNOWN,
 }
-use std::cell::RefCell;
+
+impl PartType {
+    pub const ALL: [PartType; 5] = [
+        PartType::ALPHA,
+        PartType::BETA,
+        PartType::GAMMA,
+        PartType::MUON,
+        PartType::UNKNOWN,
+    ];
+}
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Thresholds used by [`Particle::particle_type_with`].
+/// The size and energy defaults are the original hardcoded thresholds, but the default types
+/// differ from the original classification: straight tracks (see
+/// [`ClassifierConfig::muon_max_linearity`]) are MUON, short ones included, and a Bragg peak
+/// (see [`ClassifierConfig::alpha_min_bragg_ratio`]) makes a long high-energy track ALPHA.
+/// Only the checks that 0 turns off are opt-in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClassifierConfig {
+    /// Tracks with fewer pixels than this are GAMMA
+    pub min_short_size: usize,
+    /// Tracks with at least this many pixels use the long-track rules
+    pub min_long_size: usize,
+    /// Max pixel energy below which a short track counts as low-energy
+    pub short_low_max_energy: f32,
+    /// Max pixel energy below which a long track counts as low-energy
+    pub long_low_max_energy: f32,
+    /// Average energy below which a short track counts as low-energy
+    pub short_low_avg_energy: f32,
+    /// Average energy below which a long track counts as low-energy
+    pub long_low_avg_energy: f32,
+    /// Max pixel energy a short track must exceed to be considered ALPHA
+    pub alpha_min_max_energy: f32,
+    /// Max pixel energy a long track that isn't low-energy must reach to be considered
+    /// ALPHA; below it the track is UNKNOWN
+    pub long_alpha_min_max_energy: f32,
+    /// Roundness a high-energy track must exceed to be ALPHA
+    pub alpha_min_roundness: f32,
+    /// Winding a low-energy long track must exceed to be BETA
+    pub beta_min_winding: f32,
+    /// Linearity below which a low-energy track counts as straight: MUON for long tracks
+    /// regardless of winding, MUON for short ones unless they wind
+    pub muon_max_linearity: f32,
+    /// Branch points at which a low-energy long track that isn't straight counts as BETA
+    /// (an electron with a delta ray) even if it doesn't wind. 0 turns the check off
+    pub beta_min_branches: usize,
+    /// Tip-to-body energy ratio (see [`Particle::bragg_ratio`]) above which a long,
+    /// high-energy track that isn't round still counts as ALPHA
+    pub alpha_min_bragg_ratio: f32,
+    /// Eccentricity below which a high-energy track that fails the roundness test still
+    /// counts as ALPHA; unlike the hull-based roundness it shrugs off a stray pixel.
+    /// 0 turns the check off
+    pub alpha_max_eccentricity: f32,
+    /// Box-counting dimension (see [`Particle::fractal_dimension`]) above which a low-energy
+    /// long track that isn't straight counts as BETA, catching squiggles that double back on
+    /// themselves and so barely wind. 0 turns the check off
+    pub beta_min_fractal_dimension: f32,
+    /// [`Particle::mean_width`] below which a low-energy long track that doesn't wind is
+    /// MUON, even with the stray branch points pixel noise gives a thin line; about 1.5
+    /// keeps a one-pixel line in and anything with a second row of pixels out. 0 turns the
+    /// check off
+    pub muon_max_mean_width: f32,
+    /// [`Particle::mean_width`] above which a high-energy track that fails the roundness
+    /// test still counts as ALPHA, a fat cluster rather than a line; about 3 suits alphas
+    /// seen at an angle. 0 turns the check off
+    pub alpha_min_mean_width: f32,
+    /// Pixel value the detector saturates at; the confidence of a track reaching it (see
+    /// [`Particle::is_saturated`]) is halved. 0 turns the check off
+    pub saturation_level: f32,
+    /// Factor the convex hull perimeter is scaled by in the roundness tests (see
+    /// [`Particle::roundness_with`]), undoing the overestimate of perimeters measured over
+    /// pixel staircases; Kulpa's 0.948 is the usual value for digital curves. 0 turns the
+    /// correction off
+    pub roundness_perimeter_factor: f32,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        ClassifierConfig {
+            min_short_size: 4,
+            min_long_size: 50,
+            short_low_max_energy: 150.0,
+            long_low_max_energy: 100.0,
+            short_low_avg_energy: 40.0,
+            long_low_avg_energy: 40.0,
+            alpha_min_max_energy: 100.0,
+            long_alpha_min_max_energy: 100.0,
+            alpha_min_roundness: 0.4,
+            beta_min_winding: 1.0,
+            muon_max_linearity: 0.05,
+            beta_min_branches: 0,
+            alpha_min_bragg_ratio: 2.0,
+            alpha_max_eccentricity: 0.0,
+            beta_min_fractal_dimension: 0.0,
+            muon_max_mean_width: 0.0,
+            alpha_min_mean_width: 0.0,
+            saturation_level: 0.0,
+            roundness_perimeter_factor: 0.0,
+        }
+    }
+}
+
+/// How negative (e.g. baseline-subtracted) pixel values count towards a particle's energy.
+///
+/// The extractor never puts non-positive pixels into a track, so this only matters for
+/// particles built from arbitrary coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NegativeEnergy {
+    /// Sum pixel values as they are
+    #[default]
+    Include,
+    /// Count negative pixel values as zero
+    Clamp,
+}
+
+/// Endpoints and branch points of a track's pixel adjacency graph
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// Pixels with exactly one neighbour
+    pub endpoints: usize,
+    /// Pixels with three or more neighbours
+    pub branch_points: usize,
+}
+
+/// Plain, cache-free view of a [`Particle`] for storing extracted tracks
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParticleRecord {
+    pub track: Vec<(usize, usize)>,
+    /// Type under the default classifier, if it was computed when the record was made
+    pub part_type: Option<PartType>,
+}
+
+/// A track pixel outside the frame it was checked against, see
+/// [`Particle::from_coords_checked`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfBounds {
+    pub pixel: (usize, usize),
+    pub width: usize,
+    pub height: usize,
+}
+
+impl std::fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (x, y) = self.pixel;
+        write!(
+            f,
+            "pixel ({x}, {y}) lies outside the {}×{} frame",
+            self.width, self.height
+        )
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+/// A lazily computed value of a [`Particle`]. The lock makes particles `Sync`, so threads can
+/// share one; two threads asking at once may both compute the value, but they store the
+/// same thing.
+struct Cache<T>(Mutex<Option<T>>);
+
+impl<T: Clone> Cache<T> {
+    fn get(&self) -> Option<T> {
+        self.lock().clone()
+    }
+
+    fn set(&self, value: T) {
+        *self.lock() = Some(value);
+    }
+
+    /// A panic elsewhere can't leave a half-written value, so a poisoned lock is still fine
+    fn lock(&self) -> MutexGuard<'_, Option<T>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Cache(Mutex::new(None))
+    }
+}
+
+impl<T: Clone> Clone for Cache<T> {
+    fn clone(&self) -> Self {
+        Cache(Mutex::new(self.get()))
+    }
+}
 
 #[derive(Clone)]
 pub struct Particle {
     track: Vec<(usize, usize)>,
-    total_energy_cache: RefCell<Option<f32>>,
-    roundness_cache: RefCell<Option<f32>>,
-    winding_cache: RefCell<Option<f32>>,
-    part_type_cache: RefCell<Option<PartType>>,
+    negative_energy: NegativeEnergy,
+    /// Type set by [`Particle::with_type`], returned instead of classifying
+    fixed_type: Option<PartType>,
+    /// The type cache holds the [`Particle::fingerprint`] of the grid it was computed on,
+    /// so querying another frame recomputes rather than reusing it. Only the type is worth
+    /// caching per grid: the energies cost no more to sum than to fingerprint.
+    roundness_cache: Cache<f32>,
+    winding_cache: Cache<f32>,
+    linearity_cache: Cache<f32>,
+    eccentricity_cache: Cache<f32>,
+    orientation_cache: Cache<f32>,
+    fractal_cache: Cache<f32>,
+    mean_width_cache: Cache<f32>,
+    centroid_cache: Cache<(f32, f32)>,
+    path_cache: Cache<Vec<(usize, usize)>>,
+    topology_cache: Cache<Topology>,
+    part_type_cache: Cache<(ClassifierConfig, u64, PartType)>,
 }
 
 impl Particle {
     pub fn new(track: Vec<(usize, usize)>) -> Self {
         Particle {
             track,
-            total_energy_cache: RefCell::new(None),
-            roundness_cache: RefCell::new(None),
-            winding_cache: RefCell::new(None),
-            part_type_cache: RefCell::new(None),
+            negative_energy: NegativeEnergy::Include,
+            fixed_type: None,
+            roundness_cache: Cache::default(),
+            winding_cache: Cache::default(),
+            linearity_cache: Cache::default(),
+            eccentricity_cache: Cache::default(),
+            orientation_cache: Cache::default(),
+            fractal_cache: Cache::default(),
+            mean_width_cache: Cache::default(),
+            centroid_cache: Cache::default(),
+            path_cache: Cache::default(),
+            topology_cache: Cache::default(),
+            part_type_cache: Cache::default(),
+        }
+    }
+
+    /// Builds the particle formed by the connected cluster of cells above `threshold`
+    /// around `seed`, or `None` if the seed itself is not above the threshold.
+    pub fn from_grid_region(
+        grid: &[Vec<f32>],
+        threshold: f32,
+        seed: (usize, usize),
+    ) -> Option<Particle> {
+        let track = crate::particle_extractor::flood_fill(grid, seed, threshold);
+        if track.is_empty() {
+            None
+        } else {
+            Some(Particle::new(track))
         }
     }
 
+    /// [`Particle::new`] for hand-made tracks: checks every `(x, y)` pixel lies in a
+    /// `width`×`height` frame, so a bad coordinate is reported here instead of panicking
+    /// once a feature indexes the grid with it
+    pub fn from_coords_checked(
+        track: Vec<(usize, usize)>,
+        width: usize,
+        height: usize,
+    ) -> Result<Particle, OutOfBounds> {
+        match track.iter().find(|&&(x, y)| x >= width || y >= height) {
+            Some(&pixel) => Err(OutOfBounds {
+                pixel,
+                width,
+                height,
+            }),
+            None => Ok(Particle::new(track)),
+        }
+    }
+
+    /// Record of the track, classified against `grid` if one is given
+    pub fn to_record(&self, grid: Option<&[Vec<f32>]>) -> ParticleRecord {
+        ParticleRecord {
+            track: self.track.clone(),
+            part_type: grid.map(|grid| self.particle_type(grid)),
+        }
+    }
+
+    /// Rebuilds a particle from a record; the stored type is not trusted and gets
+    /// recomputed on demand.
+    pub fn from_record(record: ParticleRecord) -> Self {
+        Particle::new(record.track)
+    }
+
+    /// Switches how negative pixel values are summed, dropping any cached type.
+    pub fn with_negative_energy(mut self, mode: NegativeEnergy) -> Self {
+        self.negative_energy = mode;
+        self.part_type_cache = Cache::default();
+        self
+    }
+
+    /// Gives the particle a known type, which the built-in classification then returns with
+    /// full confidence whatever the shape, e.g. for the GAMMA clusters of
+    /// [`crate::particle_extractor::merge_gammas`]
+    pub fn with_type(mut self, part_type: PartType) -> Self {
+        self.fixed_type = Some(part_type);
+        self.part_type_cache = Cache::default();
+        self
+    }
+
+    /// The type set with [`Particle::with_type`], if any
+    pub fn fixed_type(&self) -> Option<PartType> {
+        self.fixed_type
+    }
+
+    /// Track pixels as `(x, y)`, i.e. `(column, row)` into a row-major grid.
     pub fn get_track(&self) -> Vec<(usize, usize)> {
         self.track.clone()
     }
+
+    /// Borrowed view of [`Particle::get_track`], for loops that run every frame
+    pub fn track_ref(&self) -> &[(usize, usize)] {
+        &self.track
+    }
     pub fn size(&self) -> usize {
         self.track.len()
     }
 
+    /// Sum of `grid[y][x]` over the track pixels.
     pub fn total_energy(&self, grid: &[Vec<f32>]) -> f32 {
-        if let Some(val) = *self.total_energy_cache.borrow() {
-            return val;
-        }
-
-        let energy: f32 = self.track.iter().map(|&(x, y)| grid[x][y]).sum();
+        self.track
+            .iter()
+            .map(|&(x, y)| self.energy_at(grid, x, y))
+            .sum()
+    }
 
-        *self.total_energy_cache.borrow_mut() = Some(energy);
-        energy
+    /// Hash of the grid values under the track, which are all the grid-dependent
+    /// properties read; two grids that agree there give the same results, wherever they
+    /// live and however they were edited.
+    fn fingerprint(&self, grid: &[Vec<f32>]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for &(x, y) in &self.track {
+            grid[y][x].to_bits().hash(&mut hasher);
+        }
+        hasher.finish()
     }
 
     pub fn max_energy(&self, grid: &[Vec<f32>]) -> f32 {
         self.track
             .iter()
-            .map(|&(x, y)| grid[x][y])
+            .map(|&(x, y)| self.energy_at(grid, x, y))
             .fold(0.0, |acc, val| acc.max(val))
     }
 
+    fn energy_at(&self, grid: &[Vec<f32>], x: usize, y: usize) -> f32 {
+        match self.negative_energy {
+            NegativeEnergy::Include => grid[y][x],
+            NegativeEnergy::Clamp => grid[y][x].max(0.0),
+        }
+    }
+
     pub fn avg_energy(&self, grid: &[Vec<f32>]) -> f32 {
         self.total_energy(grid) / self.size() as f32
     }
 
+    /// Min and max `(x, y)` corners of the track, inclusive
+    pub fn bounding_box(&self) -> ((usize, usize), (usize, usize)) {
+        if self.track.is_empty() {
+            return ((0, 0), (0, 0));
+        }
+
+        self.track.iter().fold(
+            ((usize::MAX, usize::MAX), (0, 0)),
+            |((min_x, min_y), (max_x, max_y)), &(x, y)| {
+                ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+            },
+        )
+    }
+
+    /// Whether any pixel lies on the border of a `width`×`height` frame, meaning the track
+    /// probably left the sensor and is incomplete
+    pub fn touches_edge(&self, width: usize, height: usize) -> bool {
+        self.track
+            .iter()
+            .any(|&(x, y)| x == 0 || y == 0 || x + 1 == width || y + 1 == height)
+    }
+
+    /// Whether any pixel reached `max_value`, the level the detector saturates at, so the
+    /// measured energy is only a lower bound
+    pub fn is_saturated(&self, grid: &[Vec<f32>], max_value: f32) -> bool {
+        self.track.iter().any(|&(x, y)| grid[y][x] >= max_value)
+    }
+
+    /// Longer side of the bounding box over the shorter one; 1.0 for a single pixel
+    pub fn aspect_ratio(&self) -> f32 {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounding_box();
+        let width = (max_x - min_x + 1) as f32;
+        let height = (max_y - min_y + 1) as f32;
+
+        width.max(height) / width.min(height)
+    }
+
+    /// Geometric center of the track pixels.
+    pub fn centroid(&self) -> (f32, f32) {
+        if let Some(val) = self.centroid_cache.get() {
+            return val;
+        }
+
+        let val = weighted_centroid(self.track.iter().map(|&(x, y)| (x, y, 1.0)));
+        self.centroid_cache.set(val);
+        val
+    }
+
+    /// Center of the track pixels weighted by their energy. Falls back to the geometric
+    /// centroid when the track has no positive total energy.
+    pub fn energy_centroid(&self, grid: &[Vec<f32>]) -> (f32, f32) {
+        if self.total_energy(grid) <= 0.0 {
+            return self.centroid();
+        }
+
+        weighted_centroid(
+            self.track
+                .iter()
+                .map(|&(x, y)| (x, y, self.energy_at(grid, x, y) as f64)),
+        )
+    }
+
     pub fn roundness(&self) -> f32 {
-        if let Some(val) = *self.roundness_cache.borrow() {
+        if let Some(val) = self.roundness_cache.get() {
             return val;
         }
 
         let val = roundness(&self.track); // CALL YOUR HELPER HERE
-        *self.roundness_cache.borrow_mut() = Some(val);
+        self.roundness_cache.set(val);
         val
     }
 
+    /// [`Particle::roundness`] with the hull perimeter scaled by
+    /// [`ClassifierConfig::roundness_perimeter_factor`], capped at 1 as no shape is rounder
+    /// than a disc. The plain roundness while the factor is 0.
+    pub fn roundness_with(&self, config: &ClassifierConfig) -> f32 {
+        let factor = config.roundness_perimeter_factor;
+        if factor > 0.0 {
+            (self.roundness() / (factor * factor)).min(1.0)
+        } else {
+            self.roundness()
+        }
+    }
+
+    /// Net turning along the track in revolutions, regardless of direction
     pub fn winding(&self) -> f32 {
-        if let Some(val) = *self.winding_cache.borrow() {
+        self.signed_winding().abs()
+    }
+
+    /// Net turning along the track in revolutions: positive when it turns clockwise as
+    /// displayed (rows growing downwards), negative when counterclockwise. The path is walked
+    /// from the end farther from the centroid to the nearer one, i.e. inwards along a spiral
+    /// as an electron curls while slowing down, so the sign reflects the handedness of the
+    /// shape rather than which end the scan met first.
+    pub fn signed_winding(&self) -> f32 {
+        if let Some(val) = self.winding_cache.get() {
             return val;
         }
 
-        let val = winding_of_path(&self.track).abs(); // CALL YOUR HELPER HERE
-        *self.winding_cache.borrow_mut() = Some(val);
+        let mut path = self.ordered_path();
+        let (cx, cy) = self.centroid();
+        let distance = |&(x, y): &(usize, usize)| (x as f32 - cx).hypot(y as f32 - cy);
+        if let (Some(first), Some(last)) = (path.first(), path.last())
+            && distance(first) < distance(last)
+        {
+            path.reverse();
+        }
+        let val = winding_of_path(&path);
+        self.winding_cache.set(val);
         val
     }
 
+    /// Box-counting dimension of the track pixels: the slope of log(boxes hit) against
+    /// log(1 / box size) over box sizes 1, 2, 4, ... below the track's extent. About 1 for a
+    /// line, towards 2 for a track that fills its area; 0 when it spans fewer than 3 pixels.
+    pub fn fractal_dimension(&self) -> f32 {
+        if let Some(val) = self.fractal_cache.get() {
+            return val;
+        }
+
+        let val = box_counting_dimension(&self.track);
+        self.fractal_cache.set(val);
+        val
+    }
+
+    /// Eccentricity `sqrt(1 - minor / major)` of the ellipse given by the pixel covariance
+    /// eigenvalues: 0 for a circular blob, approaching 1 for a line.
+    pub fn eccentricity(&self) -> f32 {
+        if let Some(val) = self.eccentricity_cache.get() {
+            return val;
+        }
+
+        let (major, minor) = covariance_eigenvalues(&self.track);
+        let val = if major > 0.0 {
+            (1.0 - minor / major).sqrt() as f32
+        } else {
+            0.0
+        };
+        self.eccentricity_cache.set(val);
+        val
+    }
+
+    /// Angle of the principal axis (the major covariance eigenvector) in radians, in
+    /// `[0, π)`, measured from the +x (column) axis towards +y (row, i.e. down the grid).
+    /// 0 for tracks too round or small to have one.
+    pub fn orientation(&self) -> f32 {
+        if let Some(val) = self.orientation_cache.get() {
+            return val;
+        }
+
+        let (sxx, syy, sxy) = covariance(&self.track);
+        let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+        let angle = if angle < 0.0 { angle + PI } else { angle } as f32;
+        // tiny negative angles can round up to π in f32, which is the same axis as 0
+        let val = if angle >= std::f32::consts::PI {
+            0.0
+        } else {
+            angle
+        };
+        self.orientation_cache.set(val);
+        val
+    }
+
+    /// The thinned track as a sequential walk from one end, which is what the winding and
+    /// other along-the-track measures need; `track` itself is in row-major scan order.
+    pub fn ordered_path(&self) -> Vec<(usize, usize)> {
+        if let Some(val) = self.path_cache.get() {
+            return val;
+        }
+
+        let val = order_path(&skeleton(&self.track));
+        self.path_cache.set(val.clone());
+        val
+    }
+
+    /// Length of the track measured along [`Particle::ordered_path`]: the sum of the steps
+    /// between consecutive path pixels, √2 for a diagonal one. Unlike [`Particle::size`] it
+    /// doesn't grow with the track's width.
+    pub fn path_length(&self) -> f32 {
+        self.ordered_path()
+            .windows(2)
+            .map(|step| step_length(step[0], step[1]))
+            .sum()
+    }
+
+    /// Thickness of the track: its pixels per unit of [`Particle::path_length`], counting
+    /// the end pixels, so 1 for a straight one-pixel-wide line and about the side for a
+    /// filled square.
+    pub fn mean_width(&self) -> f32 {
+        if let Some(val) = self.mean_width_cache.get() {
+            return val;
+        }
+
+        let val = self.size() as f32 / (self.path_length() + 1.0);
+        self.mean_width_cache.set(val);
+        val
+    }
+
+    /// Energy along the track as `(distance, energy)` pairs, following
+    /// [`Particle::ordered_path`] from its starting end. The distance is the arc length walked
+    /// so far, so a diagonal step counts √2.
+    pub fn energy_profile(&self, grid: &[Vec<f32>]) -> Vec<(f32, f32)> {
+        let path = self.ordered_path();
+        let mut distance = 0.0f32;
+
+        path.iter()
+            .enumerate()
+            .map(|(i, &(x, y))| {
+                if i > 0 {
+                    distance += step_length(path[i - 1], (x, y));
+                }
+                (distance, self.energy_at(grid, x, y))
+            })
+            .collect()
+    }
+
+    /// Mean energy of the hotter end of the [`Particle::energy_profile`] (its first or last
+    /// fifth) over the mean energy of the rest of the track. Well above 1 when the deposit
+    /// piles up at the tip, as at an alpha's Bragg peak; 0 for profiles shorter than 5 samples.
+    pub fn bragg_ratio(&self, grid: &[Vec<f32>]) -> f32 {
+        let (start, end) = self.bragg_ratios(grid);
+        start.max(end)
+    }
+
+    /// [`Particle::bragg_ratio`] of the starting and the far end of the profile separately
+    fn bragg_ratios(&self, grid: &[Vec<f32>]) -> (f32, f32) {
+        let profile = self.energy_profile(grid);
+        if profile.len() < 5 {
+            return (0.0, 0.0);
+        }
+
+        let tip = profile.len() / 5;
+        let mean = |samples: &[(f32, f32)]| {
+            samples.iter().map(|&(_, e)| e).sum::<f32>() / samples.len() as f32
+        };
+        let (head, rest) = profile.split_at(tip);
+        let (body, tail) = profile.split_at(profile.len() - tip);
+
+        let ratio = |tip: f32, body: f32| tip / body.max(f32::EPSILON);
+        (ratio(mean(head), mean(rest)), ratio(mean(tail), mean(body)))
+    }
+
+    /// Direction of travel of a track that stops in a Bragg peak (see
+    /// [`Particle::has_bragg_peak`]): the `(start, tip)` ends of its
+    /// [`Particle::ordered_path`], the tip being the hot end the particle came to rest at.
+    /// `None` without a clear peak.
+    pub fn bragg_direction(
+        &self,
+        grid: &[Vec<f32>],
+        config: &ClassifierConfig,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let (start, end) = self.bragg_ratios(grid);
+        if start.max(end) <= config.alpha_min_bragg_ratio {
+            return None;
+        }
+
+        let path = self.ordered_path();
+        let (first, last) = (*path.first()?, *path.last()?);
+        if end > start {
+            Some((first, last))
+        } else {
+            Some((last, first))
+        }
+    }
+
+    /// Whether the track ends in a pronounced energy peak, by the
+    /// [`ClassifierConfig::alpha_min_bragg_ratio`] of `config`, as the classification sees it.
+    pub fn has_bragg_peak(&self, grid: &[Vec<f32>], config: &ClassifierConfig) -> bool {
+        self.bragg_ratio(grid) > config.alpha_min_bragg_ratio
+    }
+
+    /// Ratio of the smaller to the larger eigenvalue of the pixel covariance matrix
+    /// (a total least squares line fit). Near 0 for a straight track, near 1 for a blob.
+    pub fn linearity(&self) -> f32 {
+        if let Some(val) = self.linearity_cache.get() {
+            return val;
+        }
+
+        let (major, minor) = covariance_eigenvalues(&self.track);
+        let val = if major > 0.0 {
+            (minor / major) as f32
+        } else {
+            0.0
+        };
+        self.linearity_cache.set(val);
+        val
+    }
+
+    /// Endpoint and branch point counts of the track (see [`topology`]).
+    pub fn topology(&self) -> Topology {
+        if let Some(val) = self.topology_cache.get() {
+            return val;
+        }
+
+        let val = topology(&self.track);
+        self.topology_cache.set(val);
+        val
+    }
+
+    /// Winding of the track after Douglas-Peucker simplification with the given tolerance,
+    /// so that pixel-level jitter doesn't add spurious turning. Not cached.
+    pub fn winding_simplified(&self, tolerance: f64) -> f32 {
+        let line: LineString<f64> = self
+            .ordered_path()
+            .iter()
+            .map(|&(x, y)| Coord {
+                x: x as f64,
+                y: y as f64,
+            })
+            .collect();
+
+        let simplified: Vec<(f64, f64)> = line
+            .simplify(tolerance)
+            .coords()
+            .map(|c| (c.x, c.y))
+            .collect();
+
+        winding_of_coords(&simplified).abs()
+    }
+
+    /// Classifies the particle with the default [`ClassifierConfig`].
     pub fn particle_type(&self, grid: &[Vec<f32>]) -> PartType {
-        if let Some(pt) = *self.part_type_cache.borrow() {
+        self.particle_type_with(grid, &ClassifierConfig::default())
+    }
+
+    /// Classifies the particle using the thresholds in `config`.
+    /// The result is cached together with the config and grid it was computed for.
+    pub fn particle_type_with(&self, grid: &[Vec<f32>], config: &ClassifierConfig) -> PartType {
+        let fingerprint = self.fingerprint(grid);
+        if let Some(pt) = self.cached_type(config, fingerprint) {
             return pt;
         }
 
-        let pt = match self.size() {
-            0..4 => return PartType::GAMMA,
-            4..50 => {
-                if self.max_energy(grid) < 150.0 && self.avg_energy(grid) < 40.0 {
-                    #[allow(clippy::if_same_then_else)]
-                    if self.winding() < 1.0 {
-                        PartType::BETA
-                    } else {
-                        PartType::BETA
-                    }
-                } else if self.max_energy(grid) > 100.0 {
-                    if self.roundness() > 0.4 {
-                        PartType::ALPHA
-                    } else {
-                        PartType::UNKNOWN
-                    }
+        let (pt, _) = self.decide(grid, config);
+        self.part_type_cache.set((*config, fingerprint, pt));
+        pt
+    }
+
+    /// The type cached for `config` on the grid with `fingerprint`, if any
+    fn cached_type(&self, config: &ClassifierConfig, fingerprint: u64) -> Option<PartType> {
+        match self.part_type_cache.get() {
+            Some((cached_config, cached_grid, pt))
+                if cached_config == *config && cached_grid == fingerprint =>
+            {
+                Some(pt)
+            }
+            _ => None,
+        }
+    }
+
+    /// Type under the default [`ClassifierConfig`] plus a 0..1 confidence (see
+    /// [`Particle::confidence_with`]).
+    pub fn classify_with_confidence(&self, grid: &[Vec<f32>]) -> (PartType, f32) {
+        let config = ClassifierConfig::default();
+        (
+            self.particle_type_with(grid, &config),
+            self.confidence_with(grid, &config),
+        )
+    }
+
+    /// How clearly the particle falls on its side of the thresholds in `config`: the smallest
+    /// distance of any energy or shape feature compared along the decision path from its
+    /// threshold, relative to that threshold and capped at 1. Near 0 means a nudge would change
+    /// the type. Saturated tracks get half of that, as their energies are unreliable. Not
+    /// cached.
+    pub fn confidence_with(&self, grid: &[Vec<f32>], config: &ClassifierConfig) -> f32 {
+        let confidence = self.decide(grid, config).1;
+        if config.saturation_level > 0.0 && self.is_saturated(grid, config.saturation_level) {
+            confidence * 0.5
+        } else {
+            confidence
+        }
+    }
+
+    fn decide(&self, grid: &[Vec<f32>], config: &ClassifierConfig) -> (PartType, f32) {
+        if let Some(pt) = self.fixed_type {
+            return (pt, 1.0);
+        }
+        // pixel counts are exact, so only the measured features feed the margin
+        let mut m = Margin(1.0);
+
+        let pt = if self.size() < config.min_short_size {
+            PartType::GAMMA
+        } else if self.size() < config.min_long_size {
+            if m.below(self.max_energy(grid), config.short_low_max_energy)
+                && m.below(self.avg_energy(grid), config.short_low_avg_energy)
+            {
+                // straight short tracks are muons crossing the sensor at a steep angle,
+                // anything curved is an electron
+                if m.below(self.winding(), config.beta_min_winding)
+                    && m.below(self.linearity(), config.muon_max_linearity)
+                {
+                    PartType::MUON
                 } else {
-                    PartType::UNKNOWN
+                    PartType::BETA
                 }
-            }
-            50.. => {
-                if self.max_energy(grid) < 100.0 && self.avg_energy(grid) < 40.0 {
-                    if self.winding() > 1.0 {
-                        PartType::BETA
-                    } else {
-                        PartType::MUON
-                    }
-                } else if self.max_energy(grid) < 100.0 {
-                    PartType::UNKNOWN
-                } else if self.roundness() > 0.4 {
+            } else if m.above(self.max_energy(grid), config.alpha_min_max_energy) {
+                if m.above(self.roundness_with(config), config.alpha_min_roundness)
+                    || self.eccentricity() < config.alpha_max_eccentricity
+                    || (config.alpha_min_mean_width > 0.0
+                        && m.above(self.mean_width(), config.alpha_min_mean_width))
+                {
                     PartType::ALPHA
                 } else {
                     PartType::UNKNOWN
                 }
+            } else {
+                PartType::UNKNOWN
             }
+        } else if m.below(self.max_energy(grid), config.long_low_max_energy)
+            && m.below(self.avg_energy(grid), config.long_low_avg_energy)
+        {
+            if m.below(self.linearity(), config.muon_max_linearity)
+                || (config.muon_max_mean_width > 0.0
+                    && m.below(self.mean_width(), config.muon_max_mean_width)
+                    && m.below(self.winding(), config.beta_min_winding))
+            {
+                PartType::MUON
+            } else if m.above(self.winding(), config.beta_min_winding)
+                || (config.beta_min_branches > 0
+                    && !m.below(
+                        self.topology().branch_points as f32,
+                        config.beta_min_branches as f32,
+                    ))
+                || (config.beta_min_fractal_dimension > 0.0
+                    && m.above(self.fractal_dimension(), config.beta_min_fractal_dimension))
+            {
+                PartType::BETA
+            } else {
+                PartType::MUON
+            }
+        } else if m.below(self.max_energy(grid), config.long_alpha_min_max_energy) {
+            PartType::UNKNOWN
+        } else if m.above(self.roundness_with(config), config.alpha_min_roundness)
+            || self.eccentricity() < config.alpha_max_eccentricity
+            || (config.alpha_min_mean_width > 0.0
+                && m.above(self.mean_width(), config.alpha_min_mean_width))
+            || m.above(self.bragg_ratio(grid), config.alpha_min_bragg_ratio)
+        {
+            PartType::ALPHA
+        } else {
+            PartType::UNKNOWN
         };
 
-        *self.part_type_cache.borrow_mut() = Some(pt);
-        pt
+        (pt, m.0)
+    }
+}
+
+/// Smallest relative distance from a threshold seen while classifying
+struct Margin(f32);
+
+impl Margin {
+    fn below(&mut self, value: f32, threshold: f32) -> bool {
+        self.record(value, threshold);
+        value < threshold
+    }
+
+    fn above(&mut self, value: f32, threshold: f32) -> bool {
+        self.record(value, threshold);
+        value > threshold
+    }
+
+    fn record(&mut self, value: f32, threshold: f32) {
+        let scale = if threshold == 0.0 {
+            1.0
+        } else {
+            threshold.abs()
+        };
+        let margin = ((value - threshold).abs() / scale).min(1.0);
+        // NaN features (e.g. a degenerate hull) give no confidence at all
+        self.0 = self.0.min(if margin.is_nan() { 0.0 } else { margin });
+    }
+}
+
+/// For every particle, the index of its nearest neighbour (by centroid) and the distance to it.
+/// Returned in the same order as `particles`; empty if there are fewer than two particles.
+pub fn nearest_neighbor_distances(particles: &[Particle]) -> Vec<(usize, f32)> {
+    if particles.len() < 2 {
+        return Vec::new();
+    }
+
+    let centroids: Vec<(f32, f32)> = particles.iter().map(|p| p.centroid()).collect();
+
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, &(xi, yi))| {
+            centroids
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(j, &(xj, yj))| (j, (xi - xj).hypot(yi - yj)))
+                .fold(
+                    (0, f32::INFINITY),
+                    |best, cur| {
+                        if cur.1 < best.1 { cur } else { best }
+                    },
+                )
+        })
+        .collect()
+}
+
+/// Types of all `particles` under `config`, the same as [`Particle::particle_type_with`]
+/// gives each, cached the same way. With the `rayon` feature the particles are classified
+/// on the thread pool.
+pub fn classify_all(
+    particles: &[Particle],
+    grid: &[Vec<f32>],
+    config: &ClassifierConfig,
+) -> Vec<PartType> {
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let particles = particles.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let particles = particles.iter();
+
+    particles
+        .map(|p| p.particle_type_with(grid, config))
+        .collect()
+}
+
+/// Pairs up particles of two stacked frames, such as the two planes of a telescope, whose
+/// centroids lie within `tolerance` pixels of each other. Each particle is in at most one
+/// `(index into tracks_a, index into tracks_b)` pair, the closest candidates claiming each
+/// other first. Sorted by the index into `tracks_a`.
+pub fn match_coincidences(
+    tracks_a: &[Particle],
+    tracks_b: &[Particle],
+    tolerance: f32,
+) -> Vec<(usize, usize)> {
+    let centroids_b: Vec<(f32, f32)> = tracks_b.iter().map(|p| p.centroid()).collect();
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (i, particle) in tracks_a.iter().enumerate() {
+        let (xa, ya) = particle.centroid();
+        for (j, &(xb, yb)) in centroids_b.iter().enumerate() {
+            let distance = (xa - xb).hypot(ya - yb);
+            if distance <= tolerance {
+                candidates.push((distance, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut taken_a = vec![false; tracks_a.len()];
+    let mut taken_b = vec![false; tracks_b.len()];
+    let mut pairs = Vec::new();
+    for (_, i, j) in candidates {
+        if !taken_a[i] && !taken_b[j] {
+            taken_a[i] = true;
+            taken_b[j] = true;
+            pairs.push((i, j));
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Mean of `(x, y)` points weighted by the third component
+fn weighted_centroid(points: impl Iterator<Item = (usize, usize, f64)>) -> (f32, f32) {
+    let (mut sum_x, mut sum_y, mut total) = (0.0, 0.0, 0.0);
+    for (x, y, w) in points {
+        sum_x += x as f64 * w;
+        sum_y += y as f64 * w;
+        total += w;
+    }
+
+    if total == 0.0 {
+        return (0.0, 0.0);
+    }
+    ((sum_x / total) as f32, (sum_y / total) as f32)
+}
+
+/// Euclidean distance between two pixels
+fn step_length(a: (usize, usize), b: (usize, usize)) -> f32 {
+    (a.0.abs_diff(b.0) as f32).hypot(a.1.abs_diff(b.1) as f32)
+}
+
+/// See [`Particle::fractal_dimension`]
+fn box_counting_dimension(points: &[(usize, usize)]) -> f32 {
+    let (Some(min_x), Some(max_x)) = (
+        points.iter().map(|p| p.0).min(),
+        points.iter().map(|p| p.0).max(),
+    ) else {
+        return 0.0;
+    };
+    let min_y = points.iter().map(|p| p.1).min().unwrap_or(0);
+    let max_y = points.iter().map(|p| p.1).max().unwrap_or(0);
+    let extent = (max_x - min_x).max(max_y - min_y) + 1;
+
+    let mut samples: Vec<(f64, f64)> = Vec::new();
+    let mut boxes = std::collections::HashSet::new();
+    let mut size = 1;
+    while size < extent {
+        boxes.clear();
+        boxes.extend(
+            points
+                .iter()
+                .map(|&(x, y)| ((x - min_x) / size, (y - min_y) / size)),
+        );
+        samples.push(((1.0 / size as f64).ln(), (boxes.len() as f64).ln()));
+        size *= 2;
+    }
+    if samples.len() < 2 {
+        return 0.0;
+    }
+
+    // least-squares slope
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| s.0).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.1).sum::<f64>() / n;
+    let sxy: f64 = samples
+        .iter()
+        .map(|s| (s.0 - mean_x) * (s.1 - mean_y))
+        .sum();
+    let sxx: f64 = samples.iter().map(|s| (s.0 - mean_x).powi(2)).sum();
+    (sxy / sxx) as f32
+}
+
+/// Eigenvalues `(larger, smaller)` of the 2×2 covariance matrix of the points
+fn covariance_eigenvalues(points: &[(usize, usize)]) -> (f64, f64) {
+    let (sxx, syy, sxy) = covariance(points);
+    let half_trace = (sxx + syy) / 2.0;
+    let spread = (((sxx - syy) / 2.0).powi(2) + sxy * sxy).sqrt();
+
+    (half_trace + spread, (half_trace - spread).max(0.0))
+}
+
+/// Population covariance `(var x, var y, cov xy)` of the points
+fn covariance(points: &[(usize, usize)]) -> (f64, f64, f64) {
+    if points.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y as f64).sum::<f64>() / n;
+
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for &(x, y) in points {
+        let dx = x as f64 - mean_x;
+        let dy = y as f64 - mean_y;
+        sxx += dx * dx;
+        syy += dy * dy;
+        sxy += dx * dy;
+    }
+    (sxx / n, syy / n, sxy / n)
+}
+
+/// Counts endpoints and branch points of the 8-connected pixel graph.
+///
+/// A diagonal neighbour only counts when neither of the two pixels bridging it orthogonally is
+/// in the track, so the corners of a staircase line don't show up as branches. Meant for thin
+/// tracks; every interior pixel of a thick blob has many neighbours.
+fn topology(points: &[(usize, usize)]) -> Topology {
+    let cells: std::collections::HashSet<(i64, i64)> =
+        points.iter().map(|&(x, y)| (x as i64, y as i64)).collect();
+
+    let mut topology = Topology::default();
+    for &(x, y) in &cells {
+        let mut degree = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) || !cells.contains(&(x + dx, y + dy)) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && (cells.contains(&(x + dx, y)) || cells.contains(&(x, y + dy))) {
+                    continue;
+                }
+                degree += 1;
+            }
+        }
+
+        match degree {
+            1 => topology.endpoints += 1,
+            3.. => topology.branch_points += 1,
+            _ => {}
+        }
     }
+
+    topology
 }
 
+/// Isoperimetric ratio `4πA / P²` of the convex hull: 1 for a disc, smaller for elongated
+/// shapes. Degenerate hulls (fewer than three pixels, or all of them collinear) have no area
+/// and give 0 rather than NaN.
 fn roundness(points: &[(usize, usize)]) -> f32 {
+    if points.len() < 3 {
+        return 0.0;
+    }
+
     let mp: MultiPoint<f64> = points
         .iter()
         .map(|&(x, y)| Coord {
@@ -142,11 +1066,158 @@ fn roundness(points: &[(usize, usize)]) -> f32 {
 
     let area = hull.unsigned_area();
     let perimeter = Euclidean.length(hull.exterior());
+    if area <= f64::EPSILON || perimeter <= f64::EPSILON {
+        return 0.0;
+    }
 
     (4.0 * PI * area / (perimeter * perimeter)) as f32
 }
 
+/// Thins the track to a one-pixel-wide skeleton (Zhang-Suen), keeping the input order.
+/// Falls back to the track itself if thinning would erase it, as for a 2×2 block.
+fn skeleton(points: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    if points.is_empty() {
+        return Vec::new();
+    }
+
+    // bitmap of the bounding box with a one-pixel empty border
+    let (min_x, min_y) = points
+        .iter()
+        .fold((usize::MAX, usize::MAX), |(mx, my), &(x, y)| {
+            (mx.min(x), my.min(y))
+        });
+    let width = points.iter().map(|&(x, _)| x - min_x).max().unwrap() + 3;
+    let height = points.iter().map(|&(_, y)| y - min_y).max().unwrap() + 3;
+    let mut on = vec![vec![false; width]; height];
+    for &(x, y) in points {
+        on[y - min_y + 1][x - min_x + 1] = true;
+    }
+
+    loop {
+        let mut changed = false;
+        for step in 0..2 {
+            let mut remove = Vec::new();
+            for y in 1..height - 1 {
+                for x in 1..width - 1 {
+                    if !on[y][x] {
+                        continue;
+                    }
+                    // P2..P9, clockwise from north
+                    let p = [
+                        on[y - 1][x],
+                        on[y - 1][x + 1],
+                        on[y][x + 1],
+                        on[y + 1][x + 1],
+                        on[y + 1][x],
+                        on[y + 1][x - 1],
+                        on[y][x - 1],
+                        on[y - 1][x - 1],
+                    ];
+                    let neighbours = p.iter().filter(|&&v| v).count();
+                    let transitions = (0..8).filter(|&i| !p[i] && p[(i + 1) % 8]).count();
+                    let (n, e, s, w) = (p[0], p[2], p[4], p[6]);
+                    let clear = if step == 0 {
+                        !(e && s && (n || w))
+                    } else {
+                        !(n && w && (e || s))
+                    };
+                    if (2..=6).contains(&neighbours) && transitions == 1 && clear {
+                        remove.push((x, y));
+                    }
+                }
+            }
+            changed |= !remove.is_empty();
+            for (x, y) in remove {
+                on[y][x] = false;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let thinned: Vec<(usize, usize)> = points
+        .iter()
+        .copied()
+        .filter(|&(x, y)| on[y - min_y + 1][x - min_x + 1])
+        .collect();
+    if thinned.is_empty() {
+        points.to_vec()
+    } else {
+        thinned
+    }
+}
+
+/// Orders pixels into a walk: starts at the pixel with the fewest neighbours (an endpoint of
+/// an open path) and repeatedly steps to the closest unvisited pixel, preferring orthogonal
+/// neighbours over diagonal ones and jumping across gaps when a branch runs out.
+fn order_path(points: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let index: std::collections::HashMap<(usize, usize), usize> =
+        points.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+    let neighbour = |(x, y): (usize, usize), dx: isize, dy: isize| {
+        let nx = x.checked_add_signed(dx)?;
+        let ny = y.checked_add_signed(dy)?;
+        index.get(&(nx, ny)).copied()
+    };
+    const STEPS: [(isize, isize); 8] = [
+        (1, 0),
+        (0, 1),
+        (-1, 0),
+        (0, -1),
+        (1, 1),
+        (-1, 1),
+        (-1, -1),
+        (1, -1),
+    ];
+
+    let start = (0..points.len())
+        .min_by_key(|&i| {
+            STEPS
+                .iter()
+                .filter(|&&(dx, dy)| neighbour(points[i], dx, dy).is_some())
+                .count()
+        })
+        .unwrap();
+
+    let mut visited = vec![false; points.len()];
+    let mut path = Vec::with_capacity(points.len());
+    let mut current = start;
+    loop {
+        visited[current] = true;
+        path.push(points[current]);
+        if path.len() == points.len() {
+            break;
+        }
+
+        let (cx, cy) = points[current];
+        current = STEPS
+            .iter()
+            .filter_map(|&(dx, dy)| neighbour(points[current], dx, dy))
+            .find(|&j| !visited[j])
+            .unwrap_or_else(|| {
+                (0..points.len())
+                    .filter(|&j| !visited[j])
+                    .min_by_key(|&j| {
+                        let (x, y) = points[j];
+                        x.abs_diff(cx).pow(2) + y.abs_diff(cy).pow(2)
+                    })
+                    .unwrap()
+            });
+    }
+
+    path
+}
+
 fn winding_of_path(points: &[(usize, usize)]) -> f32 {
+    let coords: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (x as f64, y as f64)).collect();
+    winding_of_coords(&coords)
+}
+
+fn winding_of_coords(points: &[(f64, f64)]) -> f32 {
     if points.len() < 3 {
         return 0.0;
     }
@@ -158,10 +1229,10 @@ fn winding_of_path(points: &[(usize, usize)]) -> f32 {
         let (x1, y1) = points[i];
         let (x2, y2) = points[i + 1];
 
-        let v1x = x1 as f64 - x0 as f64;
-        let v1y = y1 as f64 - y0 as f64;
-        let v2x = x2 as f64 - x1 as f64;
-        let v2y = y2 as f64 - y1 as f64;
+        let v1x = x1 - x0;
+        let v1y = y1 - y0;
+        let v2x = x2 - x1;
+        let v2y = y2 - y1;
 
         let cross = v1x * v2y - v1y * v2x;
         let dot = v1x * v2x + v1y * v2y;
@@ -172,3 +1243,821 @@ fn winding_of_path(points: &[(usize, usize)]) -> f32 {
 
     (total / (2.0 * PI)) as f32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x0: usize, y0: usize, side: usize) -> Particle {
+        let mut track = Vec::new();
+        for x in x0..x0 + side {
+            for y in y0..y0 + side {
+                track.push((x, y));
+            }
+        }
+        Particle::new(track)
+    }
+
+    /// Grid of `size`×`size` with the particle's pixels set to `value`
+    fn paint(particle: &Particle, value: f32, size: usize) -> Vec<Vec<f32>> {
+        let mut grid = vec![vec![0.0f32; size]; size];
+        for (x, y) in particle.get_track() {
+            grid[y][x] = value;
+        }
+        grid
+    }
+
+    #[test]
+    fn test_negative_energy() {
+        let mut grid = vec![vec![0.0f32; 4]; 4];
+        grid[0][0] = 5.0;
+        grid[0][1] = -1.0;
+        grid[0][2] = 3.0;
+        let track = vec![(0, 0), (1, 0), (2, 0)];
+
+        let included = Particle::new(track.clone());
+        let clamped = Particle::new(track).with_negative_energy(NegativeEnergy::Clamp);
+
+        assert_eq!(included.total_energy(&grid), 7.0);
+        assert_eq!(clamped.total_energy(&grid), 8.0);
+    }
+
+    #[test]
+    fn test_from_grid_region() {
+        let mut grid = vec![vec![0.0f32; 8]; 8];
+        for (x, y) in [(1, 1), (1, 2), (2, 3), (6, 6)] {
+            grid[y][x] = 10.0;
+        }
+
+        let particle = Particle::from_grid_region(&grid, 0.0, (1, 2)).unwrap();
+
+        assert_eq!(particle.size(), 3);
+        assert_eq!(particle.total_energy(&grid), 30.0);
+        assert!(Particle::from_grid_region(&grid, 0.0, (4, 4)).is_none());
+        assert!(Particle::from_grid_region(&grid, 10.0, (6, 6)).is_none());
+    }
+
+    #[test]
+    fn test_winding_simplified() {
+        // a straight line along x with pixel jitter
+        let mut track = vec![(0, 0)];
+        track.extend((1..30).map(|x| (x, 1 + x % 2)));
+        track.push((30, 0));
+        let particle = Particle::new(track);
+
+        assert!(particle.winding() > 0.2);
+        assert!(particle.winding_simplified(2.5) < 1e-6);
+    }
+
+    #[test]
+    fn test_signed_winding() {
+        // two turns spiralling inwards, clockwise on screen, and its mirror image
+        let mut clockwise: Vec<(usize, usize)> = Vec::new();
+        for i in 0..=500 {
+            let a = 4.0 * PI * i as f64 / 500.0;
+            let r = 14.0 - 8.0 * a / (4.0 * PI);
+            let point = (
+                (20.0 + r * a.cos()).round() as usize,
+                (20.0 + r * a.sin()).round() as usize,
+            );
+            if clockwise.last() != Some(&point) {
+                clockwise.push(point);
+            }
+        }
+        let counterclockwise: Vec<(usize, usize)> =
+            clockwise.iter().map(|&(x, y)| (40 - x, y)).collect();
+
+        let cw = Particle::new(clockwise).signed_winding();
+        let ccw = Particle::new(counterclockwise).signed_winding();
+        assert!(cw > 1.5, "{cw}");
+        assert!(ccw < -1.5, "{ccw}");
+        assert!((cw + ccw).abs() < 0.1, "{cw} {ccw}");
+    }
+
+    #[test]
+    fn test_s_track_winding() {
+        // an S, three pixels thick: the top arc bows left, the bottom one right
+        let mut centre: Vec<(f64, f64)> = (0..=40)
+            .map(|i| PI / 2.0 + PI * i as f64 / 40.0)
+            .map(|a| (20.0 + 8.0 * a.cos(), 12.0 - 8.0 * a.sin()))
+            .collect();
+        centre.extend(
+            (1..=40)
+                .map(|i| PI / 2.0 - PI * i as f64 / 40.0)
+                .map(|a| (20.0 + 8.0 * a.cos(), 28.0 - 8.0 * a.sin())),
+        );
+        let mut track: Vec<(usize, usize)> = Vec::new();
+        for (cx, cy) in centre {
+            let (x, y) = (cx.round() as usize, cy.round() as usize);
+            for (px, py) in [(x, y), (x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                track.push((px, py));
+            }
+        }
+        track.sort_by_key(|&(x, y)| (y, x));
+        track.dedup();
+
+        let path = order_path(&skeleton(&track));
+        let (upper, lower) = path.split_at(path.len() / 2);
+        let (w_upper, w_lower) = (winding_of_path(upper), winding_of_path(lower));
+        // thinned to a sequential walk that turns half a revolution each way
+        assert!(path.len() < track.len() / 2);
+        assert!(
+            path.windows(2)
+                .all(|w| w[0].0.abs_diff(w[1].0) <= 1 && w[0].1.abs_diff(w[1].1) <= 1)
+        );
+        assert!((w_upper + 0.5).abs() < 0.15, "{w_upper}");
+        assert!((w_lower - 0.5).abs() < 0.15, "{w_lower}");
+    }
+
+    #[test]
+    fn test_ordered_path_winding() {
+        // three quarters of a thin circle, listed in scan order like `build_tracks` does
+        let mut arc: Vec<(usize, usize)> = (0..=120)
+            .map(|i| 1.5 * PI * i as f64 / 120.0)
+            .map(|a| {
+                (
+                    (20.0 + 10.0 * a.cos()).round() as usize,
+                    (20.0 + 10.0 * a.sin()).round() as usize,
+                )
+            })
+            .collect();
+        arc.sort_by_key(|&(x, y)| (y, x));
+        arc.dedup();
+        let particle = Particle::new(arc.clone());
+
+        let scan_order = winding_of_path(&arc).abs();
+        assert!((particle.winding() - 0.75).abs() < 0.1);
+        assert!((scan_order - particle.winding()).abs() > 0.5);
+        assert_eq!(particle.ordered_path().len(), particle.size());
+    }
+
+    #[test]
+    fn test_path_length() {
+        let diagonal = Particle::new((0..20).map(|i| (i, i)).collect());
+        assert!((diagonal.path_length() - 19.0 * 2f32.sqrt()).abs() < 1e-4);
+        assert_eq!(diagonal.size(), 20);
+
+        // a band three pixels wide thins to its middle row
+        let band = Particle::new((3..6).flat_map(|y| (0..20).map(move |x| (x, y))).collect());
+        assert_eq!(band.size(), 60);
+        assert!(band.path_length() < 20.0);
+        assert_eq!(Particle::new(vec![(3, 3)]).path_length(), 0.0);
+    }
+
+    #[test]
+    fn test_mean_width() {
+        let line = Particle::new((0..40).map(|x| (x, 5)).collect());
+        let filled = square(0, 0, 10);
+        assert!((line.mean_width() - 1.0).abs() < 1e-6);
+        assert!(filled.mean_width() > 5.0, "{}", filled.mean_width());
+
+        // a fat bar that isn't round enough for ALPHA until width counts
+        let bar = Particle::new((0..4).flat_map(|y| (0..24).map(move |x| (x, y))).collect());
+        let grid = paint(&bar, 200.0, 32);
+        let by_width = ClassifierConfig {
+            alpha_min_mean_width: 3.0,
+            ..ClassifierConfig::default()
+        };
+        assert_eq!(bar.particle_type(&grid), PartType::UNKNOWN);
+        assert_eq!(bar.particle_type_with(&grid, &by_width), PartType::ALPHA);
+
+        // a thin L with a one-pixel spur: counting branches, the spur makes it BETA
+        let mut track: Vec<(usize, usize)> = (0..30).map(|x| (x, 2)).collect();
+        track.extend((3..32).map(|y| (29, y)));
+        track.push((15, 3));
+        let thin = Particle::new(track);
+        let grid = paint(&thin, 30.0, 40);
+        let by_branches = ClassifierConfig {
+            beta_min_branches: 1,
+            ..ClassifierConfig::default()
+        };
+        let by_width = ClassifierConfig {
+            muon_max_mean_width: 1.5,
+            ..by_branches
+        };
+        assert_eq!(thin.particle_type_with(&grid, &by_branches), PartType::BETA);
+        assert_eq!(thin.particle_type_with(&grid, &by_width), PartType::MUON);
+    }
+
+    #[test]
+    fn test_energy_profile() {
+        let line = Particle::new((0..10).map(|x| (x, 4)).collect());
+        let mut grid = vec![vec![0.0f32; 12]; 12];
+        for (x, cell) in grid[4].iter_mut().take(10).enumerate() {
+            *cell = 10.0 + 5.0 * x as f32;
+        }
+
+        let rising = line.energy_profile(&grid);
+        assert_eq!(rising.len(), 10);
+        assert_eq!(rising[0], (0.0, 10.0));
+        assert_eq!(rising[9], (9.0, 55.0));
+        assert!(rising.windows(2).all(|w| w[1].1 > w[0].1));
+
+        let flat = line.energy_profile(&paint(&line, 20.0, 12));
+        assert!(flat.iter().all(|&(_, e)| e == 20.0));
+
+        // diagonal steps add √2 of arc length each
+        let diagonal = Particle::new((0..4).map(|i| (i, i)).collect());
+        let profile = diagonal.energy_profile(&paint(&diagonal, 1.0, 12));
+        assert!((profile[3].0 - 3.0 * 2f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_bragg_peak() {
+        // a long straight track whose last few pixels carry most of the energy
+        let track = Particle::new((0..60).map(|x| (x, 4)).collect());
+        let mut grid = paint(&track, 120.0, 64);
+        let defaults = ClassifierConfig::default();
+        assert!(!track.has_bragg_peak(&grid, &defaults));
+        assert_eq!(track.particle_type(&grid), PartType::UNKNOWN);
+
+        for cell in &mut grid[4][50..60] {
+            *cell = 400.0;
+        }
+        assert!(track.has_bragg_peak(&grid, &defaults));
+        assert!(track.roundness() < 0.4);
+        assert_eq!(track.particle_type(&grid), PartType::ALPHA);
+
+        // a stricter config sees no peak and, agreeing, no ALPHA
+        let strict = ClassifierConfig {
+            alpha_min_bragg_ratio: 10.0,
+            ..defaults
+        };
+        assert!(!track.has_bragg_peak(&grid, &strict));
+        assert_eq!(track.particle_type_with(&grid, &strict), PartType::UNKNOWN);
+    }
+
+    #[test]
+    fn test_bragg_direction() {
+        // a straight alpha whose energy climbs towards one end, once each way round
+        let track = Particle::new((0..60).map(|x| (x, 4)).collect());
+        let mut grid = paint(&track, 120.0, 64);
+        let config = ClassifierConfig::default();
+        assert_eq!(track.bragg_direction(&grid, &config), None);
+
+        for (x, cell) in grid[4][45..60].iter_mut().enumerate() {
+            *cell = 200.0 + 40.0 * x as f32;
+        }
+        assert_eq!(
+            track.bragg_direction(&grid, &config),
+            Some(((0, 4), (59, 4)))
+        );
+        grid[4][..60].reverse();
+        assert_eq!(
+            track.bragg_direction(&grid, &config),
+            Some(((59, 4), (0, 4)))
+        );
+
+        let strict = ClassifierConfig {
+            alpha_min_bragg_ratio: 10.0,
+            ..config
+        };
+        assert_eq!(track.bragg_direction(&grid, &strict), None);
+    }
+
+    #[test]
+    fn test_caches_follow_grid() {
+        let track = Particle::new((0..60).map(|x| (x, 4)).collect());
+        let flat = paint(&track, 120.0, 64);
+        let mut peaked = flat.clone();
+        peaked[4][50..60].fill(400.0);
+
+        assert_eq!(track.particle_type(&flat), PartType::UNKNOWN);
+        assert_eq!(track.total_energy(&flat), 60.0 * 120.0);
+        assert_eq!(track.particle_type(&peaked), PartType::ALPHA);
+        assert_eq!(track.total_energy(&peaked), 50.0 * 120.0 + 10.0 * 400.0);
+        assert_eq!(track.particle_type(&flat), PartType::UNKNOWN);
+
+        // grids dropped and reallocated, likely at the same address, each get their own
+        // result
+        let dot = Particle::new(vec![(1, 1), (2, 1)]);
+        let short = ClassifierConfig {
+            min_short_size: 1,
+            short_low_max_energy: 21.0,
+            short_low_avg_energy: 21.0,
+            ..ClassifierConfig::default()
+        };
+        for i in 1..=20 {
+            let mut grid = vec![vec![0.0f32; 8]; 8];
+            grid[1][1..3].fill(2.0 * i as f32);
+            assert_eq!(dot.total_energy(&grid), 4.0 * i as f32);
+            let expected = if i <= 10 {
+                PartType::MUON
+            } else {
+                PartType::UNKNOWN
+            };
+            assert_eq!(dot.particle_type_with(&grid, &short), expected, "{i}");
+        }
+    }
+
+    #[test]
+    fn test_clone_keeps_type_cache() {
+        // the GUI filters clone classified tracks and relies on them not reclassifying
+        let particle = square(0, 0, 8);
+        let grid = paint(&particle, 200.0, 16);
+        assert!(particle.clone().part_type_cache.get().is_none());
+
+        particle.particle_type(&grid);
+        let copy = particle.clone();
+        assert_eq!(
+            copy.part_type_cache.get(),
+            Some((
+                ClassifierConfig::default(),
+                particle.fingerprint(&grid),
+                PartType::ALPHA
+            ))
+        );
+    }
+
+    #[test]
+    fn test_energy_cuts_flip_one_type() {
+        let defaults = ClassifierConfig::default();
+        let flips = |particle: &Particle, value: f32, config: ClassifierConfig| {
+            let grid = paint(particle, value, 64);
+            (
+                particle.particle_type_with(&grid, &defaults),
+                particle.particle_type_with(&grid, &config),
+            )
+        };
+
+        let blob = square(2, 2, 8);
+        let config = ClassifierConfig {
+            long_alpha_min_max_energy: 150.0,
+            ..defaults
+        };
+        assert_eq!(
+            flips(&blob, 120.0, config),
+            (PartType::ALPHA, PartType::UNKNOWN)
+        );
+
+        let long_line = Particle::new((0..60).map(|x| (x, 10)).collect());
+        let config = ClassifierConfig {
+            long_low_avg_energy: 20.0,
+            ..defaults
+        };
+        assert_eq!(
+            flips(&long_line, 30.0, config),
+            (PartType::MUON, PartType::UNKNOWN)
+        );
+
+        let short_line = Particle::new((0..20).map(|x| (x, 10)).collect());
+        let config = ClassifierConfig {
+            short_low_avg_energy: 20.0,
+            ..defaults
+        };
+        assert_eq!(
+            flips(&short_line, 30.0, config),
+            (PartType::MUON, PartType::UNKNOWN)
+        );
+    }
+
+    #[test]
+    fn test_config_keys_type_cache() {
+        let particle = square(0, 0, 8);
+        let grid = paint(&particle, 200.0, 16);
+        let strict = ClassifierConfig {
+            alpha_min_roundness: 0.99,
+            ..ClassifierConfig::default()
+        };
+
+        assert_eq!(particle.particle_type(&grid), PartType::ALPHA);
+        assert_eq!(
+            particle.particle_type_with(&grid, &strict),
+            PartType::UNKNOWN
+        );
+        assert_eq!(particle.particle_type(&grid), PartType::ALPHA);
+    }
+
+    #[test]
+    fn test_roundness_of_degenerate_tracks() {
+        let line = Particle::new((0..10).map(|x| (x, 5)).collect());
+        let diagonal = Particle::new((0..10).map(|i| (i, i)).collect());
+        for particle in [
+            &line,
+            &diagonal,
+            &Particle::new(vec![(3, 3)]),
+            &Particle::new(vec![(3, 3), (4, 3)]),
+        ] {
+            assert_eq!(particle.roundness(), 0.0);
+        }
+        assert!((square(0, 0, 8).roundness() - 0.785).abs() < 1e-3);
+
+        // a thin high-energy track is confidently not an alpha instead of comparing NaN
+        let grid = paint(&line, 200.0, 16);
+        let (ty, confidence) = line.classify_with_confidence(&grid);
+        assert_eq!(ty, PartType::UNKNOWN);
+        assert!(confidence > 0.0 && confidence.is_finite(), "{confidence}");
+    }
+
+    #[test]
+    fn test_fractal_dimension() {
+        let line = Particle::new((0..64).map(|x| (x, 7)).collect());
+        // a serpentine filling a 32×31 area: rows joined at alternating ends
+        let mut squiggle = Vec::new();
+        for row in 0..16 {
+            squiggle.extend((0..32).map(|x| (x, 2 * row)));
+            if row < 15 {
+                squiggle.push((if row % 2 == 0 { 31 } else { 0 }, 2 * row + 1));
+            }
+        }
+        squiggle.sort_by_key(|&(x, y)| (y, x));
+        let squiggle = Particle::new(squiggle);
+
+        assert!((line.fractal_dimension() - 1.0).abs() < 1e-6);
+        assert!(squiggle.fractal_dimension() > 1.5);
+        assert_eq!(Particle::new(vec![(3, 3), (4, 3)]).fractal_dimension(), 0.0);
+
+        let grid = paint(&squiggle, 20.0, 40);
+        let curly = ClassifierConfig {
+            beta_min_fractal_dimension: 1.5,
+            ..ClassifierConfig::default()
+        };
+        assert_eq!(squiggle.particle_type(&grid), PartType::MUON);
+        assert_eq!(squiggle.particle_type_with(&grid, &curly), PartType::BETA);
+    }
+
+    #[test]
+    fn test_eccentricity() {
+        let blob = square(0, 0, 10);
+        let line = Particle::new((0..60).map(|i| (10 + i, 20 + i / 3)).collect());
+
+        assert!(blob.eccentricity() < 1e-3);
+        assert!(line.eccentricity() > 0.99);
+        assert_eq!(Particle::new(vec![(3, 3)]).eccentricity(), 0.0);
+
+        // strict roundness rejects the blob; the eccentricity check lets it through
+        let grid = paint(&blob, 200.0, 16);
+        let strict = ClassifierConfig {
+            alpha_min_roundness: 0.99,
+            ..ClassifierConfig::default()
+        };
+        let with_eccentricity = ClassifierConfig {
+            alpha_max_eccentricity: 0.5,
+            ..strict
+        };
+        assert_eq!(blob.particle_type_with(&grid, &strict), PartType::UNKNOWN);
+        assert_eq!(
+            blob.particle_type_with(&grid, &with_eccentricity),
+            PartType::ALPHA
+        );
+    }
+
+    #[test]
+    fn test_orientation() {
+        let along_x = Particle::new((0..20).map(|x| (x, 3)).collect());
+        let along_y = Particle::new((0..20).map(|y| (3, y)).collect());
+        let diagonal = Particle::new((0..20).map(|i| (i, i)).collect());
+        let anti_diagonal = Particle::new((0..20).map(|i| (19 - i, i)).collect());
+
+        assert!(along_x.orientation().abs() < 1e-6);
+        assert!((along_y.orientation() - PI as f32 / 2.0).abs() < 1e-6);
+        assert!((diagonal.orientation() - PI as f32 / 4.0).abs() < 1e-6);
+        assert!((anti_diagonal.orientation() - 3.0 * PI as f32 / 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_linearity() {
+        let line = Particle::new((0..60).map(|i| (10 + i, 20 + i / 3)).collect());
+        let blob = square(0, 0, 10);
+
+        assert!(line.linearity() < 0.01);
+        assert!((blob.linearity() - 1.0).abs() < 1e-6);
+        assert_eq!(Particle::new(vec![(3, 3)]).linearity(), 0.0);
+    }
+
+    #[test]
+    fn test_straight_long_track_is_muon() {
+        // a jittery straight line whose end steps give it a little winding
+        let mut track = vec![(0, 0)];
+        track.extend((1..60).map(|x| (x, 1 + x % 2)));
+        track.push((60, 0));
+        let particle = Particle::new(track);
+        let grid = paint(&particle, 20.0, 64);
+        let borderline = ClassifierConfig {
+            beta_min_winding: 0.2,
+            ..ClassifierConfig::default()
+        };
+        let ignore_linearity = ClassifierConfig {
+            muon_max_linearity: 0.0,
+            ..borderline
+        };
+
+        assert!(particle.winding() > 0.2);
+        assert_eq!(
+            particle.particle_type_with(&grid, &borderline),
+            PartType::MUON
+        );
+        assert_eq!(
+            particle.particle_type_with(&grid, &ignore_linearity),
+            PartType::BETA
+        );
+    }
+
+    #[test]
+    fn test_short_tracks_split_on_shape() {
+        let straight = Particle::new((0..20).map(|i| (5 + i, 5 + i / 2)).collect());
+        let mut ring: Vec<(usize, usize)> = (0..64)
+            .map(|i| {
+                let a = i as f64 * 2.0 * PI / 64.0;
+                (
+                    (20.0 + 5.0 * a.cos()).round() as usize,
+                    (20.0 + 5.0 * a.sin()).round() as usize,
+                )
+            })
+            .collect();
+        ring.sort();
+        ring.dedup();
+        let curly = Particle::new(ring);
+
+        assert!(straight.size() < 50 && curly.size() < 50);
+        let grid = paint(&straight, 20.0, 32);
+        assert_eq!(straight.particle_type(&grid), PartType::MUON);
+        let grid = paint(&curly, 20.0, 32);
+        assert_eq!(curly.particle_type(&grid), PartType::BETA);
+    }
+
+    #[test]
+    fn test_topology() {
+        let staircase = Particle::new((0..30).map(|i| (i, i / 3)).collect());
+        assert_eq!(
+            staircase.topology(),
+            Topology {
+                endpoints: 2,
+                branch_points: 0
+            }
+        );
+
+        // a long low-energy track with a delta ray knocked off its middle
+        let mut track: Vec<(usize, usize)> = (0..40).map(|x| (x, 10)).collect();
+        track.extend((11..26).map(|y| (15, y)));
+        let branched = Particle::new(track);
+        let grid = paint(&branched, 20.0, 64);
+        assert_eq!(
+            branched.topology(),
+            Topology {
+                endpoints: 3,
+                branch_points: 1
+            }
+        );
+        assert!(branched.linearity() > 0.05 && branched.winding() < 1.0);
+
+        // branches only count once the check is on
+        assert_eq!(branched.particle_type(&grid), PartType::MUON);
+        let count_branches = ClassifierConfig {
+            beta_min_branches: 1,
+            ..ClassifierConfig::default()
+        };
+        assert_eq!(
+            branched.particle_type_with(&grid, &count_branches),
+            PartType::BETA
+        );
+    }
+
+    #[test]
+    fn test_classify_with_confidence() {
+        let blob = square(0, 0, 8);
+        let grid = paint(&blob, 200.0, 16);
+        let (ty, confidence) = blob.classify_with_confidence(&grid);
+        assert_eq!(ty, PartType::ALPHA);
+        assert!(confidence > 0.5, "{confidence}");
+
+        // the same blob barely over the max energy an alpha needs
+        let grid = paint(&blob, 101.0, 16);
+        let (ty, confidence) = blob.classify_with_confidence(&grid);
+        assert_eq!(ty, PartType::ALPHA);
+        assert!(confidence < 0.05, "{confidence}");
+
+        let (ty, confidence) = Particle::new(vec![(1, 1)]).classify_with_confidence(&grid);
+        assert_eq!(ty, PartType::GAMMA);
+        assert_eq!(confidence, 1.0);
+    }
+
+    #[test]
+    fn test_is_saturated() {
+        let blob = square(0, 0, 8);
+        let mut grid = paint(&blob, 200.0, 16);
+        let line = Particle::new((0..6).map(|x| (x, 12)).collect());
+        for &(x, y) in line.track_ref() {
+            grid[y][x] = 30.0;
+        }
+        grid[3][5] = 255.0;
+        assert!(blob.is_saturated(&grid, 255.0));
+        assert!(!line.is_saturated(&grid, 255.0));
+
+        let config = ClassifierConfig::default();
+        let saturating = ClassifierConfig {
+            saturation_level: 255.0,
+            ..config
+        };
+        let sure = blob.confidence_with(&grid, &config);
+        assert_eq!(blob.confidence_with(&grid, &saturating), sure * 0.5);
+        assert_eq!(
+            line.confidence_with(&grid, &saturating),
+            line.confidence_with(&grid, &config)
+        );
+        assert_eq!(
+            blob.particle_type_with(&grid, &saturating),
+            blob.particle_type_with(&grid, &config)
+        );
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let line = Particle::new((0..12).map(|i| (10 + i, 4 + i / 4)).collect());
+
+        assert_eq!(line.bounding_box(), ((10, 4), (21, 6)));
+        assert_eq!(line.aspect_ratio(), 4.0);
+        assert_eq!(Particle::new(vec![(7, 9)]).aspect_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_touches_edge() {
+        let top = Particle::new((5..10).map(|x| (x, 0)).collect());
+        let interior = square(4, 4, 3);
+
+        assert!(top.touches_edge(32, 32));
+        assert!(!interior.touches_edge(32, 32));
+        // the far border counts too, on non-square frames as well
+        assert!(interior.touches_edge(32, 7));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_record_round_trip() {
+        let particles = vec![square(0, 0, 8), square(20, 20, 2)];
+        let grid = paint(&particles[0], 200.0, 32);
+        let records: Vec<ParticleRecord> =
+            particles.iter().map(|p| p.to_record(Some(&grid))).collect();
+
+        let json = serde_json::to_string(&records).unwrap();
+        let restored: Vec<ParticleRecord> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, records);
+        for (record, particle) in restored.into_iter().zip(&particles) {
+            let rebuilt = Particle::from_record(record.clone());
+            assert_eq!(rebuilt.get_track(), particle.get_track());
+            assert_eq!(rebuilt.to_record(Some(&grid)), record);
+        }
+    }
+
+    #[test]
+    fn test_centroid() {
+        assert_eq!(square(10, 20, 3).centroid(), (11.0, 21.0));
+    }
+
+    #[test]
+    fn test_energy_centroid() {
+        // a 5-pixel row whose right end carries most of the energy
+        let particle = Particle::new((0..5).map(|x| (x, 2)).collect());
+        let mut grid = paint(&particle, 1.0, 8);
+        grid[2][4] = 16.0;
+
+        assert_eq!(particle.centroid(), (2.0, 2.0));
+        assert_eq!(particle.energy_centroid(&grid), (3.5, 2.0));
+    }
+
+    #[test]
+    fn test_nearest_neighbor_distances() {
+        // centroids at (1, 1), (4, 1) and (1, 11)
+        let particles = vec![square(0, 0, 3), square(3, 0, 3), square(0, 10, 3)];
+
+        let nn = nearest_neighbor_distances(&particles);
+
+        assert_eq!(nn, vec![(1, 3.0), (0, 3.0), (0, 10.0)]);
+        assert!(nearest_neighbor_distances(&particles[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_from_coords_checked() {
+        let grid = vec![vec![5.0f32; 8]; 4];
+        let particle = Particle::from_coords_checked(vec![(0, 0), (7, 3)], 8, 4).unwrap();
+        assert_eq!(particle.total_energy(&grid), 10.0);
+
+        let err = Particle::from_coords_checked(vec![(1, 1), (8, 2), (2, 4)], 8, 4)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            OutOfBounds {
+                pixel: (8, 2),
+                width: 8,
+                height: 4
+            }
+        );
+        assert_eq!(err.to_string(), "pixel (8, 2) lies outside the 8×4 frame");
+    }
+
+    #[test]
+    fn test_classify_all_matches_serial() {
+        // a few hundred tracks of every type: dots, lines, clumps and hot blobs
+        let mut grid = vec![vec![0.0f32; 256]; 256];
+        let mut particles = Vec::new();
+        for i in 0..320 {
+            let (x0, y0) = ((i % 20) * 12 + 1, (i / 20) * 15 + 1);
+            let track: Vec<(usize, usize)> = match i % 4 {
+                0 => vec![(x0, y0)],
+                1 => (0..10).map(|d| (x0 + d, y0 + d / 3)).collect(),
+                2 => (0..i % 7 + 3)
+                    .flat_map(|y| (0..3).map(move |x| (x0 + x, y0 + y)))
+                    .collect(),
+                _ => (0..6)
+                    .flat_map(|y| (0..6).map(move |x| (x0 + x, y0 + y)))
+                    .collect(),
+            };
+            for &(x, y) in &track {
+                grid[y][x] = 20.0 + ((x * 7 + y * 13) % 11) as f32 * (i % 4 * 15) as f32;
+            }
+            particles.push(Particle::new(track));
+        }
+        let config = ClassifierConfig::default();
+        // part of the frame already classified, as after a redraw
+        for particle in &particles[..40] {
+            particle.particle_type_with(&grid, &config);
+        }
+
+        let serial: Vec<PartType> = particles
+            .iter()
+            .map(|p| Particle::new(p.get_track()).particle_type_with(&grid, &config))
+            .collect();
+        assert!(PartType::ALL.iter().filter(|t| serial.contains(t)).count() >= 3);
+        assert_eq!(classify_all(&particles, &grid, &config), serial);
+        assert!(
+            particles
+                .iter()
+                .zip(&serial)
+                .all(|(p, &pt)| p.cached_type(&config, p.fingerprint(&grid)) == Some(pt))
+        );
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let particle = Particle::new((0..60).map(|x| (x, 4)).collect());
+        let mut grid = paint(&particle, 120.0, 64);
+        grid[4][50..60].fill(400.0);
+
+        let types: Vec<(PartType, f32)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (particle.particle_type(&grid), particle.winding())))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        assert!(types.iter().all(|&t| t == (PartType::ALPHA, 0.0)));
+        assert_eq!(
+            particle.part_type_cache.get().map(|(_, _, pt)| pt),
+            Some(PartType::ALPHA)
+        );
+    }
+
+    #[test]
+    fn test_roundness_perimeter_correction() {
+        let disk = |r: i32| {
+            Particle::new(
+                (-r..=r)
+                    .flat_map(|y| (-r..=r).map(move |x| (x, y)))
+                    .filter(|&(x, y)| x * x + y * y <= r * r)
+                    .map(|(x, y)| ((x + 10) as usize, (y + 10) as usize))
+                    .collect(),
+            )
+        };
+        let corrected = ClassifierConfig {
+            roundness_perimeter_factor: 0.948,
+            ..ClassifierConfig::default()
+        };
+
+        // a small disc stays under the cap, so the scaling shows exactly
+        let small = disk(2);
+        let plain = small.roundness();
+        assert_eq!(small.roundness_with(&ClassifierConfig::default()), plain);
+        let round = small.roundness_with(&corrected);
+        assert!(
+            (round - plain / (0.948 * 0.948)).abs() < 1e-6,
+            "{round} vs {plain}"
+        );
+        assert!(round < 1.0 && (1.0 - round) < (1.0 - plain));
+
+        // a larger one is already nearly round, and is capped at 1
+        assert_eq!(disk(4).roundness_with(&corrected), 1.0);
+    }
+
+    #[test]
+    fn test_match_coincidences() {
+        // a muon crossing both planes one pixel apart, plus unrelated hits in each
+        let front = vec![
+            square(2, 2, 3),
+            Particle::new((10..40).map(|x| (x, 20)).collect()),
+            square(60, 5, 2),
+        ];
+        let back = vec![
+            square(50, 50, 3),
+            Particle::new((11..41).map(|x| (x, 20)).collect()),
+            square(60, 12, 2),
+        ];
+
+        assert_eq!(match_coincidences(&front, &back, 2.0), vec![(1, 1)]);
+        assert_eq!(match_coincidences(&front, &back, 8.0), vec![(1, 1), (2, 2)]);
+
+        // two candidates for one track: only the closer one is paired
+        let back = vec![square(3, 2, 3), square(2, 2, 3)];
+        assert_eq!(match_coincidences(&front, &back, 2.0), vec![(0, 1)]);
+    }
+}
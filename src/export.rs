@@ -0,0 +1,184 @@
+use crate::classifier::Classifier;
+#[cfg(feature = "serde")]
+use crate::decoder::PartType;
+use crate::decoder::Particle;
+#[cfg(feature = "serde")]
+use crate::stats::FrameStats;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const CSV_HEADER: &str = "id,size,total_energy,avg_energy,max_energy,roundness,winding,linearity,centroid_x,centroid_y,type";
+
+/// Writes one row of features per particle to a CSV file at `path`.
+pub fn export_csv<P>(
+    path: P,
+    particles: &[Particle],
+    grid: &[Vec<f32>],
+    classifier: &dyn Classifier,
+) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_csv(&mut writer, particles, grid, classifier)?;
+    writer.flush()
+}
+
+/// Writes the CSV rows to any writer; floats use a fixed 4-decimal precision. Roundness is
+/// corrected as the classifier's config asks (see [`Particle::roundness_with`]).
+pub fn write_csv<W: Write>(
+    writer: &mut W,
+    particles: &[Particle],
+    grid: &[Vec<f32>],
+    classifier: &dyn Classifier,
+) -> io::Result<()> {
+    writeln!(writer, "{CSV_HEADER}")?;
+    let config = classifier.config().copied().unwrap_or_default();
+
+    for (i, particle) in particles.iter().enumerate() {
+        let (cx, cy) = particle.centroid();
+        writeln!(
+            writer,
+            "{},{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:?}",
+            i + 1,
+            particle.size(),
+            particle.total_energy(grid),
+            particle.avg_energy(grid),
+            particle.max_energy(grid),
+            particle.roundness_with(&config),
+            particle.winding(),
+            particle.linearity(),
+            cx,
+            cy,
+            classifier.classify(particle, grid),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Everything extracted from one frame, as written by [`write_report`]
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Report {
+    pub stats: FrameStats,
+    pub particles: Vec<ParticleReport>,
+}
+
+/// The features of one particle in a [`Report`], the same ones [`write_csv`] writes
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ParticleReport {
+    /// 1-based, in extraction order
+    pub id: usize,
+    pub size: usize,
+    pub total_energy: f32,
+    pub avg_energy: f32,
+    pub max_energy: f32,
+    pub roundness: f32,
+    pub winding: f32,
+    pub linearity: f32,
+    pub centroid: (f32, f32),
+    #[serde(rename = "type")]
+    pub part_type: PartType,
+}
+
+#[cfg(feature = "serde")]
+impl Report {
+    pub fn new(particles: &[Particle], grid: &[Vec<f32>], classifier: &dyn Classifier) -> Self {
+        let stats = FrameStats::compute(grid, particles, classifier);
+        let config = classifier.config().copied().unwrap_or_default();
+        let particles = particles
+            .iter()
+            .enumerate()
+            .map(|(i, particle)| ParticleReport {
+                id: i + 1,
+                size: particle.size(),
+                total_energy: particle.total_energy(grid),
+                avg_energy: particle.avg_energy(grid),
+                max_energy: particle.max_energy(grid),
+                roundness: particle.roundness_with(&config),
+                winding: particle.winding(),
+                linearity: particle.linearity(),
+                centroid: particle.centroid(),
+                part_type: classifier.classify(particle, grid),
+            })
+            .collect();
+
+        Report { stats, particles }
+    }
+}
+
+/// Writes the frame statistics and per-particle features of one frame as a JSON [`Report`]
+#[cfg(feature = "serde")]
+pub fn write_report<P>(
+    path: P,
+    particles: &[Particle],
+    grid: &[Vec<f32>],
+    classifier: &dyn Classifier,
+) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &Report::new(particles, grid, classifier))?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::HeuristicClassifier;
+
+    #[test]
+    fn test_write_csv() {
+        let mut grid = vec![vec![0.0f32; 4]; 4];
+        grid[1][1] = 2.5;
+        grid[1][2] = 1.0;
+        let particles = vec![Particle::new(vec![(1, 1), (2, 1)])];
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &particles, &grid, &HeuristicClassifier::default()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("1,2,3.5000,1.7500,2.5000,"));
+        assert!(lines[1].ends_with(",1.5000,1.0000,GAMMA"));
+        assert_eq!(lines[1].split(',').count(), lines[0].split(',').count());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_report_round_trip() {
+        let mut grid = vec![vec![0.0f32; 4]; 4];
+        grid[1][1] = 2.5;
+        grid[1][2] = 1.0;
+        grid[3][3] = 4.0;
+        let particles = vec![
+            Particle::new(vec![(1, 1), (2, 1)]),
+            Particle::new(vec![(3, 3)]),
+        ];
+        let classifier = HeuristicClassifier::default();
+        let path =
+            std::env::temp_dir().join(format!("muon_decoder_{}_report.json", std::process::id()));
+
+        write_report(&path, &particles, &grid, &classifier).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["stats"]["lit_pixels"], 3);
+        assert_eq!(json["stats"]["counts"]["GAMMA"], 2);
+        let records = json["particles"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["id"], 1);
+        assert_eq!(records[0]["type"], "GAMMA");
+        assert_eq!(records[0]["centroid"], serde_json::json!([1.5, 1.0]));
+
+        let report: Report = serde_json::from_str(&text).unwrap();
+        assert_eq!(report, Report::new(&particles, &grid, &classifier));
+    }
+}
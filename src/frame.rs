@@ -0,0 +1,740 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error, Read};
+use std::path::{Path, PathBuf};
+
+/// A value in a text frame that isn't a number, or a line with the wrong number of values;
+/// carried inside the `InvalidData` [`io::Error`] the readers return
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// 1-based line number in the file
+    pub line: usize,
+    /// 1-based position of the value within its line
+    pub column: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, value {}: {}",
+            self.line, self.column, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a grid isn't a usable frame, as found by [`check_shape`]; loaders carry it inside
+/// the `InvalidData` [`io::Error`] they return
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The frame has rows, but the first one holds no values
+    ZeroWidth,
+    /// 1-based `row` has `len` values where the first row has `width`
+    Ragged {
+        row: usize,
+        len: usize,
+        width: usize,
+    },
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeError::ZeroWidth => write!(f, "the first row has no values"),
+            ShapeError::Ragged { row, len, width } => {
+                write!(f, "row {row} has {len} values, expected {width}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+impl From<ShapeError> for io::Error {
+    fn from(e: ShapeError) -> Self {
+        Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Checks that `grid` is a proper frame, every row as long as the first and that one not
+/// empty, returning its `(width, height)`. A grid without rows is a valid, empty frame.
+/// Both the loaders and the extractor validate through here.
+pub fn check_shape(grid: &[Vec<f32>]) -> Result<(usize, usize), ShapeError> {
+    let Some(width) = grid.first().map(Vec::len) else {
+        return Ok((0, 0));
+    };
+    if width == 0 {
+        return Err(ShapeError::ZeroWidth);
+    }
+
+    match grid.iter().position(|row| row.len() != width) {
+        Some(y) => Err(ShapeError::Ragged {
+            row: y + 1,
+            len: grid[y].len(),
+            width,
+        }),
+        None => Ok((width, grid.len())),
+    }
+}
+
+/// On-disk layouts a frame can be stored in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// One row per line, values separated by whitespace
+    Whitespace,
+    /// One row per line, values separated by commas
+    Csv,
+    /// A JSON array of row arrays
+    Json,
+    /// Raw little-endian values with no header; see [`read_binary`]
+    Binary,
+}
+
+/// Element types of raw binary frames, all little-endian
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryType {
+    U16,
+    F32,
+}
+
+impl BinaryType {
+    /// Bytes per value
+    pub fn size(self) -> usize {
+        match self {
+            BinaryType::U16 => 2,
+            BinaryType::F32 => 4,
+        }
+    }
+}
+
+impl FrameFormat {
+    /// Picks the format from the file extension, defaulting to whitespace-separated text.
+    /// A trailing `.gz` is looked past, so `frame.csv.gz` is CSV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match format_extension(path.as_ref()).as_deref() {
+            Some("csv") => FrameFormat::Csv,
+            Some("json") => FrameFormat::Json,
+            Some("bin" | "raw") => FrameFormat::Binary,
+            _ => FrameFormat::Whitespace,
+        }
+    }
+}
+
+/// Extensions [`read_dir`] picks up as frames
+const FRAME_EXTENSIONS: [&str; 6] = ["txt", "dat", "csv", "json", "bin", "raw"];
+
+/// A frame of a time series and the file it was read from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub path: PathBuf,
+    pub grid: Vec<Vec<f32>>,
+}
+
+/// Reads every frame file in `dir` (by the extensions [`FrameFormat::from_path`] knows, plus
+/// `.txt` and `.dat`, optionally gzipped), in file name order, so sequences should be numbered with zero padding.
+/// Other files and subdirectories are skipped; a frame that fails to read fails the load.
+pub fn read_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Frame>, io::Error> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_frame =
+            format_extension(&path).is_some_and(|ext| FRAME_EXTENSIONS.contains(&ext.as_str()));
+        if is_frame && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let grid = read_frame(&path, FrameFormat::from_path(&path))
+                .map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+            Ok(Frame { path, grid })
+        })
+        .collect()
+}
+
+/// Reads a frame stored in the given format. Binary frames must be square so their shape
+/// can be inferred (see [`infer_binary_shape`]); use [`read_binary`] for any other.
+pub fn read_frame<P>(path: P, format: FrameFormat) -> Result<Vec<Vec<f32>>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    match format {
+        FrameFormat::Whitespace => read_lines(path),
+        FrameFormat::Csv => read_csv(path),
+        FrameFormat::Json => read_json(path),
+        FrameFormat::Binary => {
+            let bytes = read_bytes(path.as_ref())?;
+            match infer_binary_shape(&bytes) {
+                Some((side, dtype)) => parse_binary(&bytes, side, side, dtype),
+                None => Err(Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "can't infer the shape of a {}-byte binary frame; its dimensions are needed",
+                        bytes.len()
+                    ),
+                )),
+            }
+        }
+    }
+}
+
+/// Reads a raw binary frame of `height` rows of `width` values of type `dtype`
+pub fn read_binary<P>(
+    path: P,
+    width: usize,
+    height: usize,
+    dtype: BinaryType,
+) -> Result<Vec<Vec<f32>>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    parse_binary(&read_bytes(path.as_ref())?, width, height, dtype)
+}
+
+/// Side and type of the square frame the binary file contents `bytes` hold, if that can
+/// be told.
+///
+/// Twice a square number is never a square, so at most one of the types makes a square
+/// frame, but the bytes of a square frame of one type are also those of a frame of the
+/// other type with sides 1:2 (a 128×256 `f32` frame is as long as a 256×256 `u16` one).
+/// So the values decide: a file whose every word reads as a plausible `f32` pixel is only
+/// taken as `f32`, as the zero high halves of typical `u16` counts make tiny subnormals
+/// instead. `None` when the type that fits the values makes no square.
+pub fn infer_binary_shape(bytes: &[u8]) -> Option<(usize, BinaryType)> {
+    let square = |dtype: BinaryType| {
+        let len = bytes.len();
+        let values = len / dtype.size();
+        let side = values.isqrt();
+        (len.is_multiple_of(dtype.size()) && side > 0 && side * side == values)
+            .then_some((side, dtype))
+    };
+    let all_zero = bytes.iter().all(|&b| b == 0);
+    let like_f32 = bytes.len().is_multiple_of(4)
+        && bytes.chunks_exact(4).all(|word| {
+            let v = f32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            v == 0.0 || (v.is_normal() && (1e-12..1e12).contains(&v.abs()))
+        });
+
+    if all_zero {
+        // an empty frame reads the same either way
+        square(BinaryType::U16).or_else(|| square(BinaryType::F32))
+    } else if like_f32 {
+        square(BinaryType::F32)
+    } else {
+        square(BinaryType::U16)
+    }
+}
+
+/// [`infer_binary_shape`] of the binary frame at `path`, decompressed first if gzipped
+pub fn binary_shape<P>(path: P) -> Result<Option<(usize, BinaryType)>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    Ok(infer_binary_shape(&read_bytes(path.as_ref())?))
+}
+
+fn parse_binary(
+    bytes: &[u8],
+    width: usize,
+    height: usize,
+    dtype: BinaryType,
+) -> Result<Vec<Vec<f32>>, io::Error> {
+    let expected = width * height * dtype.size();
+    if bytes.len() != expected {
+        return Err(Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "a {width}×{height} {dtype:?} frame has {expected} bytes, found {}",
+                bytes.len()
+            ),
+        ));
+    }
+    if expected == 0 {
+        return Ok(vec![Vec::new(); height]);
+    }
+
+    let grid = bytes
+        .chunks_exact(width * dtype.size())
+        .map(|row| {
+            row.chunks_exact(dtype.size())
+                .map(|value| match dtype {
+                    BinaryType::U16 => u16::from_le_bytes([value[0], value[1]]) as f32,
+                    BinaryType::F32 => f32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+                })
+                .collect()
+        })
+        .collect();
+    Ok(grid)
+}
+
+pub fn read_lines<P>(filename: P) -> Result<Vec<Vec<f32>>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    RowReader::whitespace(open(filename.as_ref())?).collect()
+}
+
+pub fn read_csv<P>(filename: P) -> Result<Vec<Vec<f32>>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    RowReader::csv(open(filename.as_ref())?).collect()
+}
+
+pub fn read_json<P>(filename: P) -> Result<Vec<Vec<f32>>, io::Error>
+where
+    P: AsRef<Path>,
+{
+    let grid: Vec<Vec<f32>> = serde_json::from_reader(open(filename.as_ref())?)
+        .map_err(|e| Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    check_shape(&grid)?;
+    Ok(grid)
+}
+
+/// Whether `path` is gzip-compressed, going by a `.gz` extension
+fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// Lowercase extension that tells the format of `path`, the one before `.gz` if gzipped
+fn format_extension(path: &Path) -> Option<String> {
+    let path = if is_gzip(path) {
+        Path::new(path.file_stem()?)
+    } else {
+        path
+    };
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+/// Opens `path` for buffered reading, decompressing it on the fly if it is gzipped
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if !is_gzip(path) {
+        return Ok(Box::new(BufReader::new(file)));
+    }
+
+    #[cfg(feature = "gzip")]
+    return Ok(Box::new(BufReader::new(flate2::read::GzDecoder::new(file))));
+    #[cfg(not(feature = "gzip"))]
+    Err(Error::new(
+        io::ErrorKind::Unsupported,
+        "reading gzipped frames needs the `gzip` feature",
+    ))
+}
+
+/// The whole (decompressed) contents of `path`
+fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// How the values on a line of a text frame are separated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Separator {
+    Whitespace,
+    Comma,
+}
+
+/// Streams the data rows of a text frame one at a time.
+///
+/// Blank lines and lines starting with `#` are skipped. The line buffer is reused, the first
+/// row fixes the width every later row is allocated with, and a row of any other width is a
+/// [`ParseError`] at the first missing or extra value. Nothing is allocated for errors unless a value actually fails to parse.
+pub struct RowReader<R> {
+    reader: R,
+    separator: Separator,
+    line: String,
+    line_number: usize,
+    width: Option<usize>,
+}
+
+impl<R: BufRead> RowReader<R> {
+    /// Rows of whitespace-separated values
+    pub fn whitespace(reader: R) -> Self {
+        RowReader::new(reader, Separator::Whitespace)
+    }
+
+    /// Rows of comma-separated values
+    pub fn csv(reader: R) -> Self {
+        RowReader::new(reader, Separator::Comma)
+    }
+
+    fn new(reader: R, separator: Separator) -> Self {
+        RowReader {
+            reader,
+            separator,
+            line: String::new(),
+            line_number: 0,
+            width: None,
+        }
+    }
+
+    fn parse_row<'a>(&self, tokens: impl Iterator<Item = &'a str>) -> io::Result<Vec<f32>> {
+        let mut row = Vec::with_capacity(self.width.unwrap_or(0));
+        for (column, val) in tokens.enumerate() {
+            let value = val.parse::<f32>().map_err(|e| {
+                let error = ParseError {
+                    line: self.line_number,
+                    column: column + 1,
+                    reason: format!("`{val}`: {e}"),
+                };
+                Error::new(io::ErrorKind::InvalidData, error)
+            })?;
+            row.push(value);
+        }
+        Ok(row)
+    }
+}
+
+impl<R: BufRead> Iterator for RowReader<R> {
+    type Item = io::Result<Vec<f32>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(e) => return Some(Err(e)),
+            }
+
+            let trimmed = self.line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let row = match self.separator {
+                Separator::Whitespace => self.parse_row(trimmed.split_whitespace()),
+                Separator::Comma => self.parse_row(trimmed.split(',').map(str::trim)),
+            };
+
+            return Some(row.and_then(|row| {
+                let width = *self.width.get_or_insert(row.len());
+                if row.len() == width {
+                    Ok(row)
+                } else {
+                    let error = ParseError {
+                        line: self.line_number,
+                        column: row.len().min(width) + 1,
+                        reason: format!("{} values, expected {width}", row.len()),
+                    };
+                    Err(Error::new(io::ErrorKind::InvalidData, error))
+                }
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes `contents` to a uniquely named file in the temp dir
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("muon_decoder_{}_{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_formats_agree() {
+        let expected = vec![vec![0.0, 1.5, 0.0], vec![2.0, 0.0, 3.25]];
+
+        let txt = temp_file("agree.txt", "0 1.5 0\n2 0 3.25\n");
+        let csv = temp_file("agree.csv", "0,1.5,0\n2, 0, 3.25\n");
+        let json = temp_file("agree.json", "[[0, 1.5, 0], [2, 0, 3.25]]");
+
+        for path in [&txt, &csv, &json] {
+            let grid = read_frame(path, FrameFormat::from_path(path)).unwrap();
+            assert_eq!(grid, expected);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let path = temp_file(
+            "comments.txt",
+            "# detector dump\n# rows: 2\n\n0 1.5 0\n  # mid-frame note\n\n2 0 3.25\n\n\n",
+        );
+        let grid = read_lines(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(grid, vec![vec![0.0, 1.5, 0.0], vec![2.0, 0.0, 3.25]]);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let path = temp_file("empty.txt", "");
+        let grid = read_lines(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(grid.is_empty());
+        let config = crate::particle_extractor::ExtractConfig::default();
+        assert_eq!(
+            crate::particle_extractor::extract(&grid, &config),
+            Err(crate::particle_extractor::ExtractError::EmptyGrid)
+        );
+    }
+
+    #[test]
+    fn test_ragged_file() {
+        let json = temp_file("ragged.json", "[[0, 1, 0], [2, 0]]");
+        let err = read_frame(&json, FrameFormat::Json).unwrap_err();
+        std::fs::remove_file(&json).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "row 2 has 2 values, expected 3");
+
+        // text files report the line in the file, past the header and blank lines
+        let txt = temp_file("ragged.txt", "# header\n0 1 0\n\n2 0\n0 0 0 4\n");
+        let err = read_frame(&txt, FrameFormat::Whitespace).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "line 4, value 3: 2 values, expected 3");
+        let file = File::open(&txt).unwrap();
+        let long = RowReader::whitespace(BufReader::new(file))
+            .filter_map(Result::err)
+            .nth(1)
+            .unwrap();
+        std::fs::remove_file(&txt).unwrap();
+        let parse = long
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<ParseError>()
+            .unwrap();
+        assert_eq!((parse.line, parse.column), (5, 4));
+    }
+
+    #[test]
+    fn test_empty_first_row() {
+        let path = temp_file("empty_row.json", "[[], [2, 0], [0, 1]]");
+        let err = read_frame(&path, FrameFormat::Json).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "the first row has no values");
+        assert_eq!(
+            check_shape(&[vec![], vec![1.0]]),
+            Err(ShapeError::ZeroWidth)
+        );
+        assert_eq!(check_shape(&vec![vec![0.0; 3]; 2]), Ok((3, 2)));
+        assert_eq!(check_shape(&[]), Ok((0, 0)));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let path = temp_file("bad_value.csv", "0,1,0\n2,x7,0\n");
+        let err = read_csv(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        let parse = err.get_ref().unwrap().downcast_ref::<ParseError>().unwrap();
+        assert_eq!((parse.line, parse.column), (2, 2));
+        assert_eq!(
+            err.to_string(),
+            "line 2, value 2: `x7`: invalid float literal"
+        );
+    }
+
+    #[test]
+    fn test_row_reader_large_frame() {
+        let size = 2048;
+        let mut text = String::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                text.push_str(if (x + y) % 97 == 0 { "12.5 " } else { "0 " });
+            }
+            text.push('\n');
+        }
+
+        let grid: Vec<Vec<f32>> = RowReader::whitespace(io::Cursor::new(text))
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(grid.len(), size);
+        assert!(grid.iter().all(|row| row.len() == size));
+        assert_eq!((grid[0][0], grid[0][1], grid[96][1]), (12.5, 0.0, 12.5));
+        assert_eq!(grid[size - 1][size - 1], 0.0);
+        assert_eq!(grid[size - 1][97 * 42 - (size - 1)], 12.5);
+        let lit = grid.iter().flatten().filter(|&&v| v == 12.5).count();
+        let expected = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x + y) % 97 == 0))
+            .filter(|&hit| hit)
+            .count();
+        assert_eq!(lit, expected);
+        // rows after the first are allocated at exactly the frame width
+        assert!(grid[1..].iter().all(|row| row.capacity() == size));
+    }
+
+    /// Timing against the per-line `String` parsing frames were read with before
+    /// [`RowReader`]; run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_row_reader_against_lines() {
+        let size = 4096;
+        let mut text = String::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                text.push_str(if (x + y) % 97 == 0 { "12.5 " } else { "0 " });
+            }
+            text.push('\n');
+        }
+        let path = temp_file("bench.txt", &text);
+
+        let start = std::time::Instant::now();
+        let streamed = read_lines(&path).unwrap();
+        let reader_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let lines = BufReader::new(File::open(&path).unwrap()).lines();
+        let baseline: Vec<Vec<f32>> = lines
+            .map(|line| {
+                let line = line.unwrap();
+                line.split_whitespace()
+                    .map(|val| val.parse::<f32>().unwrap())
+                    .collect()
+            })
+            .collect();
+        let baseline_time = start.elapsed();
+        std::fs::remove_file(&path).unwrap();
+
+        println!("RowReader {reader_time:?}, per-line strings {baseline_time:?}");
+        assert_eq!(streamed, baseline);
+        assert!(
+            reader_time.as_secs_f64() <= baseline_time.as_secs_f64() * 1.5,
+            "RowReader {reader_time:?} vs per-line strings {baseline_time:?}"
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let expected = vec![vec![0.0, 1.0, 700.0], vec![65535.0, 2.0, 0.0]];
+
+        let u16_bytes: Vec<u8> = expected
+            .iter()
+            .flatten()
+            .flat_map(|&v| (v as u16).to_le_bytes())
+            .collect();
+        let f32_bytes: Vec<u8> = expected
+            .iter()
+            .flatten()
+            .flat_map(|&v: &f32| (v / 4.0).to_le_bytes())
+            .collect();
+        let u16_path =
+            std::env::temp_dir().join(format!("muon_decoder_{}_u16.bin", std::process::id()));
+        let f32_path =
+            std::env::temp_dir().join(format!("muon_decoder_{}_f32.raw", std::process::id()));
+        std::fs::write(&u16_path, &u16_bytes).unwrap();
+        std::fs::write(&f32_path, &f32_bytes).unwrap();
+
+        let from_u16 = read_binary(&u16_path, 3, 2, BinaryType::U16).unwrap();
+        let from_f32 = read_binary(&f32_path, 3, 2, BinaryType::F32).unwrap();
+        let wrong_shape = read_binary(&u16_path, 4, 2, BinaryType::U16).unwrap_err();
+        // six values make no square, so the shape can't be guessed
+        let unknown = read_frame(&u16_path, FrameFormat::from_path(&u16_path)).unwrap_err();
+        std::fs::remove_file(&u16_path).unwrap();
+        std::fs::remove_file(&f32_path).unwrap();
+
+        assert_eq!(from_u16, expected);
+        let quartered: Vec<Vec<f32>> = expected
+            .iter()
+            .map(|row| row.iter().map(|v| v / 4.0).collect())
+            .collect();
+        assert_eq!(from_f32, quartered);
+        assert_eq!(wrong_shape.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(unknown.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_infer_binary_shape() {
+        // a sparse frame of counts, as `u16` and as calibrated `f32`
+        let values = |len: usize| (0..len).map(|i| if i % 7 == 3 { i as f32 } else { 0.0 });
+        let as_u16 =
+            |len| -> Vec<u8> { values(len).flat_map(|v| (v as u16).to_le_bytes()).collect() };
+        let as_f32 =
+            |len| -> Vec<u8> { values(len).flat_map(|v| (v * 0.75).to_le_bytes()).collect() };
+
+        assert_eq!(
+            infer_binary_shape(&as_u16(16 * 16)),
+            Some((16, BinaryType::U16))
+        );
+        assert_eq!(
+            infer_binary_shape(&as_f32(16 * 16)),
+            Some((16, BinaryType::F32))
+        );
+        // an 8×16 `f32` frame has the bytes of a 16×16 `u16` one
+        assert_eq!(as_f32(8 * 16).len(), as_u16(16 * 16).len());
+        assert_eq!(infer_binary_shape(&as_f32(8 * 16)), None);
+        assert_eq!(infer_binary_shape(&as_u16(10 * 14)), None);
+        assert_eq!(
+            infer_binary_shape(&[0; 16 * 16 * 2]),
+            Some((16, BinaryType::U16))
+        );
+        assert_eq!(infer_binary_shape(&[]), None);
+    }
+
+    #[test]
+    fn test_read_dir_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("muon_decoder_{}_series", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested.txt")).unwrap();
+        std::fs::write(dir.join("frame_01.csv"), "0,2\n0,0\n").unwrap();
+        std::fs::write(dir.join("frame_00.txt"), "1 0\n0 0\n").unwrap();
+        std::fs::write(dir.join("notes.md"), "not a frame").unwrap();
+
+        let frames = read_dir(&dir).unwrap();
+        let names: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["frame_00.txt", "frame_01.csv"]);
+        assert_eq!(frames[1].grid, vec![vec![0.0, 2.0], vec![0.0, 0.0]]);
+
+        std::fs::write(dir.join("frame_02.txt"), "1 x\n").unwrap();
+        let err = read_dir(&dir).unwrap_err();
+        assert!(err.to_string().contains("frame_02.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_matches_plain() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let contents = "# header\n0 1.5 0\n2 0 3.25\n";
+        let plain = temp_file("gz_plain.txt", contents);
+        let gzipped = std::env::temp_dir().join(format!(
+            "muon_decoder_{}_gz_frame.txt.gz",
+            std::process::id()
+        ));
+        let mut encoder = GzEncoder::new(File::create(&gzipped).unwrap(), Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(FrameFormat::from_path(&gzipped), FrameFormat::Whitespace);
+        let expected = read_frame(&plain, FrameFormat::from_path(&plain)).unwrap();
+        let grid = read_frame(&gzipped, FrameFormat::from_path(&gzipped)).unwrap();
+        std::fs::remove_file(&plain).unwrap();
+        std::fs::remove_file(&gzipped).unwrap();
+        assert_eq!(grid, expected);
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(FrameFormat::from_path("a/frame.CSV"), FrameFormat::Csv);
+        assert_eq!(FrameFormat::from_path("frame.json"), FrameFormat::Json);
+        assert_eq!(FrameFormat::from_path("frame.txt"), FrameFormat::Whitespace);
+        assert_eq!(FrameFormat::from_path("frame.raw"), FrameFormat::Binary);
+        assert_eq!(FrameFormat::from_path("frame.csv.gz"), FrameFormat::Csv);
+        assert_eq!(FrameFormat::from_path("frame"), FrameFormat::Whitespace);
+    }
+}
@@ -1,10 +1,111 @@
-use crate::decoder::{PartType, Particle};
+use crate::classifier::{Classifier, HeuristicClassifier};
+use crate::decoder::{ClassifierConfig, PartType, Particle};
+use crate::frame::{self, BinaryType, Frame, FrameFormat};
+use crate::particle_extractor::{self, ExtractConfig, ExtractError};
 use eframe::egui::{self, ColorImage};
 use rfd::FileDialog;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-enum Mode {
+/// Track color for each particle type
+const TYPE_PALETTE: [(PartType, egui::Color32); 5] = [
+    (PartType::ALPHA, egui::Color32::from_rgb(230, 60, 60)),
+    (PartType::BETA, egui::Color32::from_rgb(70, 130, 240)),
+    (PartType::GAMMA, egui::Color32::from_rgb(80, 200, 90)),
+    (PartType::MUON, egui::Color32::from_rgb(240, 220, 60)),
+    (PartType::UNKNOWN, egui::Color32::from_rgb(150, 150, 150)),
+];
+
+/// Single-mode keys that jump to the next drawn track of a type
+const TYPE_KEYS: [(egui::Key, PartType); 5] = [
+    (egui::Key::A, PartType::ALPHA),
+    (egui::Key::B, PartType::BETA),
+    (egui::Key::G, PartType::GAMMA),
+    (egui::Key::N, PartType::MUON),
+    (egui::Key::U, PartType::UNKNOWN),
+];
+
+/// Confidence below which a track's classification counts as borderline
+const LOW_CONFIDENCE: f32 = 0.1;
+
+fn type_color(ty: PartType) -> egui::Color32 {
+    TYPE_PALETTE
+        .iter()
+        .find(|(t, _)| *t == ty)
+        .map(|&(_, color)| color)
+        .unwrap_or(egui::Color32::WHITE)
+}
+
+/// Palettes for the energy heatmap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Colormap {
+    Grayscale,
+    Viridis,
+    Inferno,
+    #[default]
+    Jet,
+}
+
+impl Colormap {
+    const ALL: [Colormap; 4] = [
+        Colormap::Grayscale,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Jet,
+    ];
+
+    /// Evenly spaced colors the palette interpolates between
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+            Colormap::Viridis => &[
+                [68, 1, 84],
+                [59, 82, 139],
+                [33, 145, 140],
+                [94, 201, 98],
+                [253, 231, 37],
+            ],
+            Colormap::Inferno => &[
+                [0, 0, 4],
+                [66, 10, 104],
+                [147, 38, 103],
+                [221, 81, 58],
+                [252, 165, 10],
+                [252, 255, 164],
+            ],
+            Colormap::Jet => &[
+                [0, 0, 128],
+                [0, 0, 255],
+                [0, 128, 255],
+                [0, 255, 255],
+                [128, 255, 128],
+                [255, 255, 0],
+                [255, 128, 0],
+                [255, 0, 0],
+                [128, 0, 0],
+            ],
+        }
+    }
+
+    /// Maps a normalized energy in `0.0..=1.0` onto the palette
+    fn color(self, t: f32) -> egui::Color32 {
+        let stops = self.stops();
+        let pos = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (pos as usize).min(stops.len() - 2);
+        let k = pos - i as f32;
+        let mix = |c: usize| {
+            (stops[i][c] as f32 + (stops[i + 1][c] as f32 - stops[i][c] as f32) * k).round() as u8
+        };
+        egui::Color32::from_rgb(mix(0), mix(1), mix(2))
+    }
+}
+
+/// Whether the viewer shows one track at a time or all of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
     Single,
     Combined,
 }
@@ -18,10 +119,207 @@ impl Mode {
     }
 }
 
+/// Columns of the per-track table, each also a sort key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TrackColumn {
+    Id,
+    Size,
+    Energy,
+    Roundness,
+    Winding,
+    Type,
+}
+
+impl TrackColumn {
+    const ALL: [TrackColumn; 6] = [
+        TrackColumn::Id,
+        TrackColumn::Size,
+        TrackColumn::Energy,
+        TrackColumn::Roundness,
+        TrackColumn::Winding,
+        TrackColumn::Type,
+    ];
+
+    /// Value track `index` is sorted by in this column
+    fn key(
+        self,
+        index: usize,
+        particle: &Particle,
+        matrix: &[Vec<f32>],
+        classifier: &dyn Classifier,
+    ) -> f32 {
+        match self {
+            TrackColumn::Id => index as f32,
+            TrackColumn::Size => particle.size() as f32,
+            TrackColumn::Energy => particle.total_energy(matrix),
+            TrackColumn::Roundness => {
+                particle.roundness_with(&classifier.config().copied().unwrap_or_default())
+            }
+            TrackColumn::Winding => particle.winding(),
+            TrackColumn::Type => {
+                let ty = classifier.classify(particle, matrix);
+                PartType::ALL.iter().position(|&t| t == ty).unwrap_or(0) as f32
+            }
+        }
+    }
+}
+
+/// Order the drawn tracks are numbered and stepped through in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum TrackOrder {
+    /// Extraction order, by first pixel in a row-major scan
+    #[default]
+    Position,
+    /// Highest total energy first
+    Energy,
+    /// Most pixels first
+    Size,
+    /// Grouped by type, in [`PartType::ALL`] order
+    Type,
+}
+
+impl TrackOrder {
+    const ALL: [TrackOrder; 4] = [
+        TrackOrder::Position,
+        TrackOrder::Energy,
+        TrackOrder::Size,
+        TrackOrder::Type,
+    ];
+
+    /// Sorts `tracks`, which are in extraction order, stably so ties stay in that order
+    /// Sorts `tracks`, stably, each track's energy or type looked up once
+    fn sort(
+        self,
+        tracks: Vec<Particle>,
+        matrix: &[Vec<f32>],
+        classifier: &dyn Classifier,
+    ) -> Vec<Particle> {
+        match self {
+            TrackOrder::Position => tracks,
+            TrackOrder::Energy => {
+                let mut keyed: Vec<(f32, Particle)> = tracks
+                    .into_iter()
+                    .map(|p| (p.total_energy(matrix), p))
+                    .collect();
+                keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+                keyed.into_iter().map(|(_, p)| p).collect()
+            }
+            TrackOrder::Size => {
+                let mut tracks = tracks;
+                tracks.sort_by_key(|p| std::cmp::Reverse(p.size()));
+                tracks
+            }
+            TrackOrder::Type => {
+                let mut tracks = tracks;
+                tracks.sort_by_cached_key(|p| {
+                    let ty = classifier.classify(p, matrix);
+                    PartType::ALL.iter().position(|&t| t == ty)
+                });
+                tracks
+            }
+        }
+    }
+}
+
+/// Storage key the viewer settings are saved under
+#[cfg(feature = "persistence")]
+const SETTINGS_KEY: &str = "view_settings";
+
+/// Filter and view settings restored between sessions
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ViewSettings {
+    show_alpha: bool,
+    show_beta: bool,
+    show_gamma: bool,
+    show_muon: bool,
+    show_unknown: bool,
+    mode: Mode,
+    scale: usize,
+    /// Missing from settings saved before palettes existed
+    #[cfg_attr(feature = "serde", serde(default))]
+    colormap: Colormap,
+}
+
+/// Entries kept in the undo history
+const HISTORY_LEN: usize = 64;
+
+/// View state that undo/redo steps through: the filters and mode plus the selected track
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ViewState {
+    settings: ViewSettings,
+    current_track: usize,
+}
+
+/// Bounded undo/redo stacks around the last recorded state
+struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    current: T,
+}
+
+impl<T: Copy + PartialEq> History<T> {
+    fn new(current: T) -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current,
+        }
+    }
+
+    /// Swaps the current entry for `state` without an undo step, for adjustments that come
+    /// with restoring it rather than from the user
+    fn amend(&mut self, state: T) {
+        self.current = state;
+    }
+
+    /// Makes `state` the current entry; a state equal to the current one isn't recorded
+    fn record(&mut self, state: T) {
+        if state == self.current {
+            return;
+        }
+        if self.undo.len() == HISTORY_LEN {
+            self.undo.remove(0);
+        }
+        self.undo.push(std::mem::replace(&mut self.current, state));
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) -> Option<T> {
+        let previous = self.undo.pop()?;
+        self.redo
+            .push(std::mem::replace(&mut self.current, previous));
+        Some(previous)
+    }
+
+    fn redo(&mut self) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo.push(std::mem::replace(&mut self.current, next));
+        Some(next)
+    }
+}
+
 pub struct MatrixApp {
+    /// The frame as loaded, before [`crate::preprocess::prepare`]
+    raw: Vec<Vec<f32>>,
+    /// `raw` after preprocessing and calibration; everything is extracted and drawn from it
     matrix: Vec<Vec<f32>>,
+    classifier: Box<dyn Classifier>,
+    extract: ExtractConfig,
+    /// The gain or offset was edited but the frames not yet recalibrated, which waits
+    /// until the drag or text edit is over
+    calibration_pending: bool,
+    /// Likewise for the range or threshold and the extraction they feed
+    extraction_pending: bool,
+    /// Dark frame subtracted from every loaded frame ahead of [`crate::preprocess::prepare`]
+    background: Option<Vec<Vec<f32>>>,
+    /// Particles as extracted, before GAMMA merging, so a new merge radius needs no
+    /// re-extraction
+    extracted: Vec<Particle>,
     all_tracks: Vec<Particle>,
     tracks_to_draw: Vec<Particle>,
+    /// Merge radius the slider is set to, also while merging is off
+    gamma_radius: usize,
     scale: usize,
     current_track: usize,
     image: ColorImage,
@@ -33,14 +331,217 @@ pub struct MatrixApp {
     show_gamma: bool,
     show_muon: bool,
     show_unknown: bool,
+    /// Leave out tracks touching the frame border, which are likely cut off
+    hide_edge: bool,
+    outline_only: bool,
+    show_heatmap: bool,
+    colormap: Colormap,
+    auto_contrast: bool,
+    dim_low_confidence: bool,
+    /// Crop the view to this rectangle of cells, zoomed to the size of the whole frame
+    roi: Option<Roi>,
+    /// Index into `tracks_to_draw` of the topmost drawn track at each grid cell
+    pixel_owner: Vec<Option<usize>>,
+    /// Column the track table is sorted by, and whether largest first
+    table_sort: (TrackColumn, bool),
+    /// Indices into `tracks_to_draw` in table row order, dropped whenever the sort or the
+    /// drawn tracks change rather than rebuilt every repaint
+    table_order: Option<Vec<usize>>,
+    /// Order of `tracks_to_draw`, which Single mode steps through
+    track_order: TrackOrder,
+    size_bins: usize,
+    energy_bins: usize,
+    log: Vec<String>,
+    history: History<ViewState>,
+    /// Frame shown next to the main one for comparison, drawn with the same view settings
+    second: Option<SecondFrame>,
+    /// Centroid distance in pixels within which tracks of the two frames coincide
+    coincidence_tolerance: f32,
+    /// Extraction running in the background, if any
+    extraction: Option<Extraction>,
+    binary_prompt: Option<BinaryPrompt>,
+    /// Directory of frames being scrubbed through, the current one shown as the main frame
+    series: Option<Series>,
 }
 
-impl MatrixApp {
-    pub fn new(matrix: Vec<Vec<f32>>, tracks: Vec<Particle>, scale: usize) -> Self {
-        let mut app = Self {
+/// A time series of frames and the particles extracted from each so far
+struct Series {
+    frames: Vec<Frame>,
+    index: usize,
+    /// Particles per frame before GAMMA merging, valid for `config`
+    tracks: Vec<Option<Vec<Particle>>>,
+    config: ExtractConfig,
+    playing: bool,
+    /// Frames per second while playing
+    fps: f32,
+    /// `egui` time the shown frame was switched to
+    shown_at: f64,
+}
+
+impl Series {
+    /// Drops the cached particles if they were extracted with other settings than `config`.
+    /// The merge radius doesn't count, since merging runs on the cached particles.
+    fn check_config(&mut self, config: ExtractConfig) {
+        let config = ExtractConfig {
+            gamma_merge_radius: 0,
+            ..config
+        };
+        if self.config != config {
+            self.tracks.fill(None);
+            self.config = config;
+        }
+    }
+}
+
+/// Dimensions asked for before opening a binary frame whose shape can't be inferred
+struct BinaryPrompt {
+    path: PathBuf,
+    /// Open it as the comparison frame rather than the main one
+    second: bool,
+    width: usize,
+    height: usize,
+    dtype: BinaryType,
+}
+
+/// Particles of the main frame and, if one is open, the comparison frame
+type Extracted = Result<(Vec<Particle>, Option<Vec<Particle>>), ExtractError>;
+
+/// A background extraction job. Dropping it orphans the thread, whose result is then
+/// discarded, which is how a newer job supersedes an older one.
+struct Extraction {
+    receiver: mpsc::Receiver<Extracted>,
+    /// Whether to log the particle count once it finishes
+    announce: bool,
+    /// Index of the series frame being extracted, to cache the result under
+    frame: Option<usize>,
+}
+
+/// A comparison frame and what has been extracted and drawn from it
+struct SecondFrame {
+    raw: Vec<Vec<f32>>,
+    matrix: Vec<Vec<f32>>,
+    extracted: Vec<Particle>,
+    all_tracks: Vec<Particle>,
+    tracks_to_draw: Vec<Particle>,
+    image: ColorImage,
+}
+
+/// Sets up a [`MatrixApp`]. Anything left unset gets the viewer's default: tracks extracted
+/// from the frame, scale 2, the built-in heuristics, default extraction settings, and the
+/// palette and mode of the last session (or Jet and Combined).
+pub struct MatrixAppBuilder<'a> {
+    raw: Vec<Vec<f32>>,
+    tracks: Option<Vec<Particle>>,
+    scale: usize,
+    classifier: Option<Box<dyn Classifier>>,
+    extract: ExtractConfig,
+    colormap: Option<Colormap>,
+    mode: Option<Mode>,
+    storage: Option<&'a dyn eframe::Storage>,
+}
+
+impl<'a> MatrixAppBuilder<'a> {
+    /// `raw` is the frame as read, before [`crate::preprocess::prepare`]
+    pub fn new(raw: Vec<Vec<f32>>) -> Self {
+        MatrixAppBuilder {
+            raw,
+            tracks: None,
+            scale: 2,
+            classifier: None,
+            extract: ExtractConfig::default(),
+            colormap: None,
+            mode: None,
+            storage: None,
+        }
+    }
+
+    /// Tracks already extracted from the prepared frame with the same [`ExtractConfig`],
+    /// saving [`MatrixAppBuilder::build`] from extracting them again. They must be the
+    /// unmerged output of [`particle_extractor::extract_particles`]: the builder merges GAMMA
+    /// hits itself, so the viewer can undo or redo the merge.
+    pub fn tracks(mut self, tracks: Vec<Particle>) -> Self {
+        self.tracks = Some(tracks);
+        self
+    }
+
+    /// Screen pixels per grid cell, 1 to 8
+    pub fn scale(mut self, scale: usize) -> Self {
+        self.scale = scale.clamp(1, 8);
+        self
+    }
+
+    pub fn classifier(mut self, classifier: Box<dyn Classifier>) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Uses the built-in heuristics with the thresholds in `config`
+    pub fn classifier_config(self, config: ClassifierConfig) -> Self {
+        self.classifier(Box::new(HeuristicClassifier { config }))
+    }
+
+    pub fn extract(mut self, config: ExtractConfig) -> Self {
+        self.extract = config;
+        self
+    }
+
+    /// Heatmap palette, overriding the one restored from `storage`
+    pub fn colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = Some(colormap);
+        self
+    }
+
+    /// Initial view mode, overriding the one restored from `storage`
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Where the view settings of earlier sessions are kept, usually `cc.storage`
+    pub fn storage(mut self, storage: Option<&'a dyn eframe::Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn build(self) -> MatrixApp {
+        let classifier = self
+            .classifier
+            .unwrap_or_else(|| Box::new(HeuristicClassifier::default()));
+        let matrix = crate::preprocess::prepare(self.raw.clone(), &self.extract);
+        let mut error = None;
+        let extracted = match self.tracks {
+            Some(tracks) => tracks,
+            None => {
+                particle_extractor::extract_particles(&matrix, &self.extract).unwrap_or_else(|e| {
+                    error = Some(format!("Extraction failed: {e}"));
+                    Vec::new()
+                })
+            }
+        };
+        let tracks = particle_extractor::merge_gammas(
+            extracted.clone(),
+            &matrix,
+            classifier.as_ref(),
+            self.extract.gamma_merge_radius,
+        );
+        let (raw, scale, extract) = (self.raw, self.scale, self.extract);
+
+        let mut app = MatrixApp {
             matrix,
+            raw,
+            classifier,
+            extract,
+            calibration_pending: false,
+            extraction_pending: false,
+            background: None,
+            extracted,
             all_tracks: tracks.clone(),
             tracks_to_draw: tracks,
+            gamma_radius: if extract.gamma_merge_radius > 0 {
+                extract.gamma_merge_radius
+            } else {
+                2
+            },
             scale,
             current_track: 0,
             image: ColorImage {
@@ -49,59 +550,620 @@ impl MatrixApp {
             },
             needs_update: true,
             current_mode: Mode::Combined,
-            error: None,
+            error,
             show_alpha: true,
             show_beta: true,
             show_gamma: true,
             show_muon: true,
             show_unknown: true,
+            hide_edge: false,
+            outline_only: false,
+            show_heatmap: false,
+            colormap: Colormap::default(),
+            auto_contrast: false,
+            dim_low_confidence: false,
+            roi: None,
+            pixel_owner: Vec::new(),
+            table_sort: (TrackColumn::Id, false),
+            table_order: None,
+            track_order: TrackOrder::default(),
+            size_bins: 10,
+            energy_bins: 10,
+            log: Vec::new(),
+            history: History::new(ViewState {
+                settings: ViewSettings {
+                    show_alpha: true,
+                    show_beta: true,
+                    show_gamma: true,
+                    show_muon: true,
+                    show_unknown: true,
+                    mode: Mode::Combined,
+                    scale,
+                    colormap: Colormap::default(),
+                },
+                current_track: 0,
+            }),
+            second: None,
+            coincidence_tolerance: 2.0,
+            extraction: None,
+            binary_prompt: None,
+            series: None,
         };
+        if let Some(settings) = self.storage.and_then(load_settings) {
+            app.apply_settings(settings);
+            app.update_counter();
+        }
+        if let Some(colormap) = self.colormap {
+            app.colormap = colormap;
+        }
+        if let Some(mode) = self.mode {
+            app.current_mode = mode;
+        }
+        app.history = History::new(app.view_state());
         app.update_image();
         app
     }
+}
+
+impl MatrixApp {
+    /// The viewer `builder` describes, the same as [`MatrixAppBuilder::build`]
+    pub fn new(builder: MatrixAppBuilder) -> Self {
+        builder.build()
+    }
+
+    fn settings(&self) -> ViewSettings {
+        ViewSettings {
+            show_alpha: self.show_alpha,
+            show_beta: self.show_beta,
+            show_gamma: self.show_gamma,
+            show_muon: self.show_muon,
+            show_unknown: self.show_unknown,
+            mode: self.current_mode,
+            scale: self.scale,
+            colormap: self.colormap,
+        }
+    }
+
+    fn apply_settings(&mut self, settings: ViewSettings) {
+        self.show_alpha = settings.show_alpha;
+        self.show_beta = settings.show_beta;
+        self.show_gamma = settings.show_gamma;
+        self.show_muon = settings.show_muon;
+        self.show_unknown = settings.show_unknown;
+        self.current_mode = settings.mode;
+        self.scale = settings.scale.clamp(1, 8);
+        self.colormap = settings.colormap;
+    }
+
+    /// Sets every type filter at once, then recounts and redraws a single time
+    fn set_filters(&mut self, show: impl Fn(PartType) -> bool) {
+        self.show_alpha = show(PartType::ALPHA);
+        self.show_beta = show(PartType::BETA);
+        self.show_gamma = show(PartType::GAMMA);
+        self.show_muon = show(PartType::MUON);
+        self.show_unknown = show(PartType::UNKNOWN);
+        self.update_counter();
+        self.update_image();
+    }
+
+    fn view_state(&self) -> ViewState {
+        ViewState {
+            settings: self.settings(),
+            current_track: self.current_track,
+        }
+    }
+
+    /// Index of the first drawn track of type `ty` after the current one, wrapping around
+    fn next_of_type(&self, ty: PartType) -> Option<usize> {
+        let len = self.tracks_to_draw.len();
+        (1..=len)
+            .map(|step| (self.current_track + step) % len)
+            .find(|&i| {
+                self.classifier
+                    .classify(&self.tracks_to_draw[i], &self.matrix)
+                    == ty
+            })
+    }
+
+    /// Restores a state from the undo history and redraws with it
+    fn restore(&mut self, state: ViewState) {
+        self.apply_settings(state.settings);
+        self.update_counter();
+        self.current_track = if state.current_track < self.tracks_to_draw.len() {
+            state.current_track
+        } else {
+            0
+        };
+        // a clamped index is still the restored state, so it mustn't drop the redo steps
+        self.history.amend(self.view_state());
+        self.update_image();
+    }
 
     /// Update the image for current track or combined tracks
     fn update_image(&mut self) {
-        let size_x = self.matrix.len();
-        let size_y = self.matrix[0].len();
-        let img_x = size_x * self.scale;
-        let img_y = size_y * self.scale;
-        let mut pixels = vec![egui::Color32::BLACK; img_x * img_y];
-
-        if self.tracks_to_draw.is_empty() {
-            self.image = ColorImage {
-                size: [img_x, img_y],
-                pixels,
-            };
+        let width = self.matrix.first().map_or(0, Vec::len);
+        self.pixel_owner = vec![None; width * self.matrix.len()];
+        let options = self.render_options();
+        for index in drawn(
+            &self.matrix,
+            &self.tracks_to_draw,
+            options.mode,
+            options.current,
+        ) {
+            for &(x, y) in self.tracks_to_draw[index].track_ref() {
+                self.pixel_owner[y * width + x] = Some(index);
+            }
+        }
+
+        self.image = render_tracks(
+            &self.matrix,
+            &self.tracks_to_draw,
+            self.classifier.as_ref(),
+            &options,
+        );
+        // the second frame's tracks are numbered on their own, so Single mode shows the one
+        // coinciding with the current track, or none
+        let second_current = self.second_selection().unwrap_or(usize::MAX);
+        if let Some(second) = &mut self.second {
+            second.image = render_tracks(
+                &second.matrix,
+                &second.tracks_to_draw,
+                self.classifier.as_ref(),
+                &RenderOptions {
+                    current: second_current,
+                    ..options
+                },
+            );
+        }
+    }
+
+    /// How the current view draws tracks
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            mode: self.current_mode,
+            scale: self.scale,
+            current: self.current_track,
+            outline_only: self.outline_only,
+            show_heatmap: self.show_heatmap,
+            colormap: self.colormap,
+            auto_contrast: self.auto_contrast,
+            dim_low_confidence: self.dim_low_confidence,
+            roi: self.roi,
+        }
+    }
+
+    /// Index into the second frame's drawn tracks of the one the Coincidences panel pairs
+    /// with the current track, if any
+    fn second_selection(&self) -> Option<usize> {
+        let second = self.second.as_ref()?;
+        crate::decoder::match_coincidences(
+            &self.tracks_to_draw,
+            &second.tracks_to_draw,
+            self.coincidence_tolerance,
+        )
+        .into_iter()
+        .find(|&(a, _)| a == self.current_track)
+        .map(|(_, b)| b)
+    }
+
+    /// Screen pixels per rendered pixel: a region of interest is blown up to the size the
+    /// whole frame would take
+    fn zoom(&self) -> f32 {
+        let (width, height) = frame_size(&self.matrix);
+        if width == 0 || height == 0 {
+            return 1.0;
+        }
+        let region = view_region(self.roi, width, height);
+        (width as f32 / region.width as f32).min(height as f32 / region.height as f32)
+    }
+
+    /// Whether a frame with any deposited energy is loaded
+    fn has_frame(&self) -> bool {
+        self.matrix.iter().flatten().any(|&value| value != 0.0)
+    }
+
+    /// Re-runs extraction on the current frames with `self.extract` on a background thread,
+    /// superseding any extraction still running. The tracks shown stay until it finishes.
+    fn reextract(&mut self, announce: bool) {
+        let (sender, receiver) = mpsc::channel();
+        let matrix = self.matrix.clone();
+        let second = self.second.as_ref().map(|second| second.matrix.clone());
+        let config = self.extract;
+        std::thread::spawn(move || {
+            let result = particle_extractor::extract_particles(&matrix, &config).and_then(|main| {
+                let second = second
+                    .map(|matrix| particle_extractor::extract_particles(&matrix, &config))
+                    .transpose()?;
+                Ok((main, second))
+            });
+            // fails only when the job was superseded, and then nobody wants the result
+            let _ = sender.send(result);
+        });
+        self.extraction = Some(Extraction {
+            receiver,
+            announce,
+            frame: self.series.as_ref().map(|series| series.index),
+        });
+    }
+
+    /// Takes in the result of the background extraction once it is ready
+    fn poll_extraction(&mut self) {
+        let Some(extraction) = &self.extraction else {
+            return;
+        };
+        let (main, second) = match extraction.receiver.try_recv() {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                self.extraction = None;
+                let message = format!("Extraction failed: {e}");
+                self.log(&message);
+                self.error = Some(message);
+                return;
+            }
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.extraction = None;
+                self.error = Some("Extraction failed".to_string());
+                self.log("Extraction failed");
+                return;
+            }
+        };
+        let (announce, frame) = (extraction.announce, extraction.frame);
+        self.extraction = None;
+
+        if let Some(series) = &mut self.series
+            && let Some(index) = frame
+        {
+            series.check_config(self.extract);
+            series.tracks[index] = Some(main.clone());
+        }
+        self.extracted = main;
+        if let Some(frame) = &mut self.second
+            && let Some(tracks) = second
+        {
+            frame.extracted = tracks;
+        }
+        // GAMMA merging needs the classifier, which stays on this thread
+        self.merge();
+
+        if announce {
+            let mut message = format!("Extracted {} particles", self.all_tracks.len());
+            if let Some(frame) = &self.second {
+                message += &format!(", {} in the second frame", frame.all_tracks.len());
+            }
+            self.log(message);
+        }
+    }
+
+    /// Merges the GAMMA hits of the extracted particles with the current radius, then
+    /// classifies and redraws the result
+    fn merge(&mut self) {
+        let radius = self.extract.gamma_merge_radius;
+        self.all_tracks = particle_extractor::merge_gammas(
+            self.extracted.clone(),
+            &self.matrix,
+            self.classifier.as_ref(),
+            radius,
+        );
+        if let Some(frame) = &mut self.second {
+            frame.all_tracks = particle_extractor::merge_gammas(
+                frame.extracted.clone(),
+                &frame.matrix,
+                self.classifier.as_ref(),
+                radius,
+            );
+        }
+        self.classify_tracks();
+        self.update_counter();
+        self.update_image();
+    }
+
+    /// `[start, tip]` cells of the arrow drawn over the track shown in Single mode: from
+    /// where an ALPHA entered towards the Bragg peak it stopped in, if it has a clear one
+    fn travel_arrow(&self) -> Option<[(usize, usize); 2]> {
+        if self.current_mode != Mode::Single {
+            return None;
+        }
+        let particle = self.tracks_to_draw.get(self.current_track)?;
+        if self.classifier.classify(particle, &self.matrix) != PartType::ALPHA {
+            return None;
+        }
+        let (start, tip) = particle.bragg_direction(&self.matrix, &self.classifier_config())?;
+        Some([start, tip])
+    }
+
+    /// Thresholds of the classifier in use, the defaults for one without
+    fn classifier_config(&self) -> ClassifierConfig {
+        self.classifier.config().copied().unwrap_or_default()
+    }
+
+    /// How many particles of the main frame GAMMA merging folded into others
+    fn merged_count(&self) -> usize {
+        self.extracted.len().saturating_sub(self.all_tracks.len())
+    }
+
+    /// Opens the frame at `path` as the main or (with `second`) the comparison frame.
+    /// Binary frames that aren't square ask for their dimensions first.
+    fn open(&mut self, path: PathBuf, second: bool) {
+        let format = FrameFormat::from_path(&path);
+        if format == FrameFormat::Binary
+            && let Ok(None) = frame::binary_shape(&path)
+        {
+            self.binary_prompt = Some(BinaryPrompt {
+                path,
+                second,
+                width: self.matrix.first().map_or(0, Vec::len),
+                height: self.matrix.len(),
+                dtype: BinaryType::U16,
+            });
             return;
         }
 
-        let tracks_to_draw: Vec<Vec<(usize, usize)>> = match self.current_mode {
-            Mode::Single => vec![self.tracks_to_draw[self.current_track].get_track()],
-            Mode::Combined => self.tracks_to_draw.iter().map(|p| p.get_track()).collect(),
+        let result = frame::read_frame(&path, format);
+        self.show_frame(&path, result, second);
+    }
+
+    /// Puts a read frame on screen and starts extracting it, reporting unreadable or empty
+    /// files in the log and the error popup instead
+    fn show_frame(&mut self, path: &Path, result: std::io::Result<Vec<Vec<f32>>>, second: bool) {
+        let mat = match result {
+            Ok(mat) if mat.iter().all(Vec::is_empty) => {
+                let message = format!("{} contains no values", path.display());
+                self.log(&message);
+                self.error = Some(message);
+                return;
+            }
+            Ok(mat) => mat,
+            Err(e) => {
+                let message = format!("Failed to load {}: {e}", path.display());
+                self.log(&message);
+                self.error = Some(message);
+                return;
+            }
         };
 
-        for track_cells in tracks_to_draw {
-            let color = egui::Color32::WHITE;
-            for (x, y) in track_cells {
-                for dx in 0..self.scale {
-                    for dy in 0..self.scale {
-                        let px = x * self.scale + dx;
-                        let py = y * self.scale + dy;
-                        if px < img_x && py < img_y {
-                            pixels[px * img_y + py] = color;
-                        }
-                    }
-                }
+        let prepared = self.prepare(mat.clone());
+        if second {
+            self.second = Some(SecondFrame {
+                raw: mat,
+                matrix: prepared,
+                extracted: Vec::new(),
+                all_tracks: Vec::new(),
+                tracks_to_draw: Vec::new(),
+                image: ColorImage::new([1, 1], egui::Color32::BLACK),
+            });
+            self.log(format!("Loaded {} for comparison", path.display()));
+        } else {
+            self.raw = mat;
+            self.matrix = prepared;
+            self.series = None;
+            // the old tracks don't belong to the new frame, so show none until done
+            self.extracted.clear();
+            self.all_tracks.clear();
+            self.update_counter();
+            self.update_image();
+            self.log.clear();
+            self.log(format!("Loaded {}", path.display()));
+        }
+        self.reextract(true);
+    }
+
+    /// Starts scrubbing through the frames in `dir`, showing the first one
+    fn open_series(&mut self, dir: &Path) {
+        let frames = match frame::read_dir(dir) {
+            Ok(frames) if frames.is_empty() => {
+                self.error = Some(format!("{} contains no frames", dir.display()));
+                return;
+            }
+            Ok(frames) => frames,
+            Err(e) => {
+                let message = format!("Failed to load {}: {e}", dir.display());
+                self.log(&message);
+                self.error = Some(message);
+                return;
+            }
+        };
+
+        self.series = Some(Series {
+            tracks: vec![None; frames.len()],
+            frames,
+            index: 0,
+            config: ExtractConfig {
+                gamma_merge_radius: 0,
+                ..self.extract
+            },
+            playing: false,
+            fps: 2.0,
+            shown_at: 0.0,
+        });
+        self.show_series_frame(0);
+    }
+
+    /// Makes frame `index` of the series the main frame, reusing its particles if they were
+    /// already extracted with the current settings
+    fn show_series_frame(&mut self, index: usize) {
+        let Some(series) = &mut self.series else {
+            return;
+        };
+        series.check_config(self.extract);
+        series.index = index;
+        let frame = &series.frames[index];
+        let message = format!(
+            "Frame {} of {}: {}",
+            index + 1,
+            series.frames.len(),
+            frame.path.display()
+        );
+        self.raw = frame.grid.clone();
+        let cached = series.tracks[index].clone();
+
+        self.matrix = self.prepare(self.raw.clone());
+        self.log.clear();
+        self.log(message);
+        match cached {
+            Some(tracks) => {
+                // whatever is still running was for another frame
+                self.extraction = None;
+                self.extracted = tracks;
+                self.merge();
+            }
+            None => {
+                self.extracted.clear();
+                self.all_tracks.clear();
+                self.reextract(false);
+                self.update_counter();
+                self.update_image();
             }
         }
+    }
 
-        self.image = ColorImage {
-            size: [img_x, img_y],
-            pixels,
+    /// Steps `delta` frames through the series, wrapping around at either end
+    fn step_series(&mut self, delta: isize) {
+        if let Some(series) = &self.series {
+            let len = series.frames.len() as isize;
+            let index = (series.index as isize + delta).rem_euclid(len);
+            self.show_series_frame(index as usize);
+        }
+    }
+
+    /// Re-applies preprocessing to the loaded frames after the calibration or background
+    /// changed, then extracts them again. Tracks stay until the new extraction finishes.
+    fn recalibrate(&mut self) {
+        self.matrix = self.prepare(self.raw.clone());
+        if let Some(raw) = self.second.as_ref().map(|second| second.raw.clone()) {
+            let matrix = self.prepare(raw);
+            if let Some(second) = &mut self.second {
+                second.matrix = matrix;
+            }
+        }
+        self.update_image();
+        self.reextract(false);
+    }
+
+    /// Subtracts the background, if one is loaded, then runs [`crate::preprocess::prepare`].
+    /// A frame the background doesn't fit is reported and prepared without it.
+    fn prepare(&mut self, raw: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        let raw = match self
+            .background
+            .as_ref()
+            .map(|dark| crate::preprocess::subtract_background(&raw, dark))
+        {
+            Some(Ok(clean)) => clean,
+            Some(Err(e)) => {
+                let message = format!("Background not subtracted: {e}");
+                self.log(&message);
+                self.error = Some(message);
+                raw
+            }
+            None => raw,
+        };
+        crate::preprocess::prepare(raw, &self.extract)
+    }
+
+    /// Loads the dark frame at `path` and subtracts it from the frames from now on
+    fn open_background(&mut self, path: &Path) {
+        let dark = match frame::read_frame(path, FrameFormat::from_path(path)) {
+            Ok(dark) => dark,
+            Err(e) => {
+                let message = format!("Failed to load {}: {e}", path.display());
+                self.log(&message);
+                self.error = Some(message);
+                return;
+            }
         };
+        if let Err(e) = crate::preprocess::subtract_background(&self.raw, &dark) {
+            self.error = Some(format!("Can't use {} as background: {e}", path.display()));
+            return;
+        }
+
+        self.log(format!("Subtracting background {}", path.display()));
+        self.set_background(Some(dark));
+    }
+
+    /// Swaps the dark frame and re-prepares the frames with it. Particles cached for the
+    /// series were extracted from frames cleaned with the old one, so they are dropped.
+    fn set_background(&mut self, background: Option<Vec<Vec<f32>>>) {
+        self.background = background;
+        if let Some(series) = &mut self.series {
+            series.tracks.fill(None);
+        }
+        self.recalibrate();
+    }
+
+    /// Appends a timestamped line to the analysis log of the current frame
+    fn log(&mut self, message: impl AsRef<str>) {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let (h, m, s) = ((secs / 3600) % 24, (secs / 60) % 60, secs % 60);
+        self.log
+            .push(format!("[{h:02}:{m:02}:{s:02}] {}", message.as_ref()));
+    }
+
+    /// Classifies every extracted track once with [`Classifier::classify_all`], filling each
+    /// particle's type cache so that filtering, drawing and the track table only look the
+    /// type up afterwards
+    fn classify_tracks(&self) {
+        self.classifier.classify_all(&self.all_tracks, &self.matrix);
+        if let Some(second) = &self.second {
+            self.classifier
+                .classify_all(&second.all_tracks, &second.matrix);
+        }
     }
+
+    /// Re-applies the filters to the extracted tracks. With the types cached by
+    /// [`Self::classify_tracks`] this is cheap enough to run on every filter toggle.
+    /// The current track stays selected if it still passes, wherever the order puts it.
     fn update_counter(&mut self) {
+        // tracks are disjoint, so the first pixel identifies one
+        let current = self
+            .tracks_to_draw
+            .get(self.current_track)
+            .and_then(|p| p.track_ref().first().copied());
+
+        self.tracks_to_draw = self.filtered(&self.matrix, &self.all_tracks);
+        self.table_order = None;
+        if let Some(second) = &self.second {
+            let tracks = self.filtered(&second.matrix, &second.all_tracks);
+            if let Some(second) = &mut self.second {
+                second.tracks_to_draw = tracks;
+            }
+        }
+
+        if let Some(pixel) = current
+            && let Some(i) = self
+                .tracks_to_draw
+                .iter()
+                .position(|p| p.track_ref().first() == Some(&pixel))
+        {
+            self.current_track = i;
+        }
+        if self.current_track >= self.tracks_to_draw.len() {
+            self.current_track = 0;
+        }
+    }
+
+    /// Indices into `tracks_to_draw` sorted as `table_sort` asks, each key computed once
+    fn sorted_table(&self) -> Vec<usize> {
+        let (sort, descending) = self.table_sort;
+        let keys: Vec<f32> = self
+            .tracks_to_draw
+            .iter()
+            .enumerate()
+            .map(|(i, particle)| sort.key(i, particle, &self.matrix, self.classifier.as_ref()))
+            .collect();
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|&a, &b| keys[a].total_cmp(&keys[b]));
+        if descending {
+            order.reverse();
+        }
+        order
+    }
+
+    /// The tracks of `matrix` whose type passes the type filters, minus edge tracks when
+    /// those are hidden, in `track_order`
+    fn filtered(&self, matrix: &[Vec<f32>], tracks: &[Particle]) -> Vec<Particle> {
         let filters = [
             (self.show_alpha, PartType::ALPHA),
             (self.show_beta, PartType::BETA),
@@ -110,27 +1172,379 @@ impl MatrixApp {
             (self.show_unknown, PartType::UNKNOWN),
         ];
 
-        self.tracks_to_draw.clear();
+        let (width, height) = frame_size(matrix);
+        let tracks = tracks
+            .iter()
+            .filter(|track| !(self.hide_edge && track.touches_edge(width, height)))
+            .filter(|track| {
+                let ty = self.classifier.classify(track, matrix);
+                filters.iter().any(|&(show, t)| show && t == ty)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        self.track_order
+            .sort(tracks, matrix, self.classifier.as_ref())
+    }
+}
+
+/// `(width, height)` of a row-major frame
+fn frame_size(matrix: &[Vec<f32>]) -> (usize, usize) {
+    (matrix.first().map_or(0, Vec::len), matrix.len())
+}
+
+/// The track's type for display, marked when it touches the frame border
+fn type_label(particle: &Particle, matrix: &[Vec<f32>], classifier: &dyn Classifier) -> String {
+    let (width, height) = frame_size(matrix);
+    let ty = classifier.classify(particle, matrix);
+    if particle.touches_edge(width, height) {
+        format!("{ty:?} (edge)")
+    } else {
+        format!("{ty:?}")
+    }
+}
+
+#[cfg(feature = "persistence")]
+fn load_settings(storage: &dyn eframe::Storage) -> Option<ViewSettings> {
+    serde_json::from_str(&storage.get_string(SETTINGS_KEY)?).ok()
+}
+
+#[cfg(not(feature = "persistence"))]
+fn load_settings(_storage: &dyn eframe::Storage) -> Option<ViewSettings> {
+    None
+}
+
+/// Everything besides the frame and its tracks that decides how [`render_tracks`] draws
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RenderOptions {
+    mode: Mode,
+    scale: usize,
+    /// Track drawn in [`Mode::Single`]
+    current: usize,
+    outline_only: bool,
+    show_heatmap: bool,
+    colormap: Colormap,
+    /// Stretch the heatmap over the 1st–99th percentile of lit pixels instead of 0..max
+    auto_contrast: bool,
+    dim_low_confidence: bool,
+    /// Only this part of the frame is drawn, see [`view_region`]
+    roi: Option<Roi>,
+}
+
+/// A rectangle of grid cells, `x` and `y` being its top-left corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Roi {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Roi {
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// The cells of a nonempty `width`×`height` frame that get drawn: the part of `roi` inside
+/// the frame, at least one cell, or the whole frame without one
+fn view_region(roi: Option<Roi>, width: usize, height: usize) -> Roi {
+    let Some(roi) = roi else {
+        return Roi {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+    };
+    let (x, y) = (roi.x.min(width - 1), roi.y.min(height - 1));
+    Roi {
+        x,
+        y,
+        width: roi.width.clamp(1, width - x),
+        height: roi.height.clamp(1, height - y),
+    }
+}
+
+/// The `low` and `high` quantiles (nearest rank, 0..1) of `values`; `(0, 0)` when empty
+fn percentile_range(values: &[f32], low: f32, high: f32) -> (f32, f32) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable_by(f32::total_cmp);
+    let at = |q: f32| sorted[((sorted.len() - 1) as f32 * q).round() as usize];
+    (at(low), at(high))
+}
+
+/// Indices of the tracks shown in `mode`, in painting order: where tracks overlap, the one
+/// with more energy (then more pixels, then the later index) is painted last and wins
+fn drawn(matrix: &[Vec<f32>], tracks: &[Particle], mode: Mode, current: usize) -> Vec<usize> {
+    let indices: Vec<usize> = match mode {
+        Mode::Single if current < tracks.len() => vec![current],
+        Mode::Single => Vec::new(),
+        Mode::Combined => (0..tracks.len()).collect(),
+    };
+    let energies: Vec<f32> = indices
+        .iter()
+        .map(|&i| tracks[i].total_energy(matrix))
+        .collect();
+    let mut order: Vec<usize> = (0..indices.len()).collect();
+    order.sort_by(|&a, &b| {
+        energies[a]
+            .total_cmp(&energies[b])
+            .then(tracks[indices[a]].size().cmp(&tracks[indices[b]].size()))
+            .then(indices[a].cmp(&indices[b]))
+    });
+    order.into_iter().map(|i| indices[i]).collect()
+}
+
+/// Draws `tracks` of `matrix` into an image `options.scale` pixels per cell, colored by the
+/// type `classifier` gives them. With a region of interest only its cells are drawn, tracks
+/// clipped to it. Nothing outside the arguments is read.
+fn render_tracks(
+    matrix: &[Vec<f32>],
+    tracks: &[Particle],
+    classifier: &dyn Classifier,
+    options: &RenderOptions,
+) -> ColorImage {
+    let width = matrix.first().map_or(0, Vec::len);
+    let height = matrix.len();
+    if width == 0 || height == 0 {
+        return ColorImage::new([1, 1], egui::Color32::BLACK);
+    }
+    let region = view_region(options.roi, width, height);
+    let img_x = region.width * options.scale;
+    let img_y = region.height * options.scale;
+    let mut pixels = vec![egui::Color32::BLACK; img_x * img_y];
 
-        for track in &self.all_tracks {
-            if filters
+    if options.show_heatmap {
+        // the contrast follows what is on screen, so a cropped view gets the full range
+        let shown = || {
+            matrix[region.y..][..region.height]
                 .iter()
-                .any(|(show, ty)| *show && track.particle_type(&self.matrix) == *ty)
-            {
-                self.tracks_to_draw.push(track.clone());
+                .flat_map(|row| &row[region.x..][..region.width])
+                .copied()
+        };
+        let (low, high) = if options.auto_contrast {
+            let lit: Vec<f32> = shown().filter(|&v| v > 0.0).collect();
+            percentile_range(&lit, 0.01, 0.99)
+        } else {
+            (0.0, shown().fold(0.0f32, f32::max))
+        };
+        for (y, row) in matrix[region.y..][..region.height].iter().enumerate() {
+            for (x, &value) in row[region.x..][..region.width].iter().enumerate() {
+                if value <= 0.0 {
+                    continue;
+                }
+                let t = if high > low {
+                    (value - low) / (high - low)
+                } else {
+                    1.0
+                };
+                let color = options.colormap.color(t);
+                for dy in 0..options.scale {
+                    let start = (y * options.scale + dy) * img_x + x * options.scale;
+                    pixels[start..start + options.scale].fill(color);
+                }
+            }
+        }
+        return ColorImage {
+            size: [img_x, img_y],
+            pixels,
+        };
+    }
+
+    for index in drawn(matrix, tracks, options.mode, options.current) {
+        let particle = &tracks[index];
+        let outline;
+        let track_cells = if options.outline_only {
+            outline = boundary_pixels(particle.track_ref());
+            &outline[..]
+        } else {
+            particle.track_ref()
+        };
+        let mut color = type_color(classifier.classify(particle, matrix));
+        if options.dim_low_confidence && classifier.confidence(particle, matrix) < LOW_CONFIDENCE {
+            color = color.gamma_multiply(0.35);
+        }
+        for &(x, y) in track_cells.iter().filter(|&&(x, y)| region.contains(x, y)) {
+            let (x, y) = (x - region.x, y - region.y);
+            for dx in 0..options.scale {
+                for dy in 0..options.scale {
+                    let px = x * options.scale + dx;
+                    let py = y * options.scale + dy;
+                    if px < img_x && py < img_y {
+                        pixels[py * img_x + px] = color;
+                    }
+                }
             }
         }
     }
+
+    ColorImage {
+        size: [img_x, img_y],
+        pixels,
+    }
+}
+
+/// Pixels of a track that have at least one 4-neighbour outside the track
+fn boundary_pixels(track: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let cells: HashSet<(usize, usize)> = track.iter().copied().collect();
+
+    track
+        .iter()
+        .copied()
+        .filter(|&(x, y)| {
+            x == 0
+                || y == 0
+                || !cells.contains(&(x - 1, y))
+                || !cells.contains(&(x + 1, y))
+                || !cells.contains(&(x, y - 1))
+                || !cells.contains(&(x, y + 1))
+        })
+        .collect()
+}
+
+/// Bins `values` into `bins` equal-width buckets spanning their range
+fn histogram(values: &[f32], bins: usize) -> (Vec<usize>, f32, f32) {
+    if values.is_empty() {
+        return (vec![0; bins.max(1)], 0.0, 0.0);
+    }
+
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    (histogram_in(values, bins, min, max), min, max)
+}
+
+/// Bins values over a fixed `min..=max` range so several series line up
+fn histogram_in(values: &[f32], bins: usize, min: f32, max: f32) -> Vec<usize> {
+    let mut counts = vec![0usize; bins.max(1)];
+    let width = (max - min) / counts.len() as f32;
+
+    for &v in values {
+        let bin = if width > 0.0 {
+            (((v - min) / width).max(0.0) as usize).min(counts.len() - 1)
+        } else {
+            0
+        };
+        counts[bin] += 1;
+    }
+
+    counts
+}
+
+/// Track index under the pointer, mapping screen position back through the on-screen
+/// `cell_size` to a cell of the drawn `region` of a frame `width` cells wide
+fn hovered_track(
+    pos: egui::Pos2,
+    rect: egui::Rect,
+    cell_size: f32,
+    region: Roi,
+    width: usize,
+    owners: &[Option<usize>],
+) -> Option<usize> {
+    let offset = pos - rect.min;
+    if offset.x < 0.0 || offset.y < 0.0 {
+        return None;
+    }
+    let x = (offset.x / cell_size) as usize;
+    let y = (offset.y / cell_size) as usize;
+    if x >= region.width || y >= region.height {
+        return None;
+    }
+    owners
+        .get((region.y + y) * width + region.x + x)
+        .copied()
+        .flatten()
+}
+
+/// Encodes the rendered view as an RGBA PNG at its scaled resolution
+fn save_png(path: &std::path::Path, image: &ColorImage) -> image::ImageResult<()> {
+    let [width, height] = image.size;
+    let rgba: Vec<u8> = image.pixels.iter().flat_map(|c| c.to_array()).collect();
+    image::save_buffer(
+        path,
+        &rgba,
+        width as u32,
+        height as u32,
+        image::ExtendedColorType::Rgba8,
+    )
+}
+
+/// Draws histogram bars scaled to the tallest bin, stacking each colored series
+fn draw_histogram(ui: &mut egui::Ui, series: &[(Vec<usize>, egui::Color32)]) {
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(20));
+
+    let bins = series.iter().map(|(c, _)| c.len()).max().unwrap_or(0);
+    let totals: Vec<usize> = (0..bins)
+        .map(|i| series.iter().filter_map(|(c, _)| c.get(i)).sum())
+        .collect();
+    let tallest = totals.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let bar_width = rect.width() / bins.max(1) as f32;
+
+    for i in 0..bins {
+        let mut bottom = rect.bottom();
+        for (counts, color) in series {
+            let count = counts.get(i).copied().unwrap_or(0);
+            let height = rect.height() * count as f32 / tallest;
+            let bar = egui::Rect::from_min_max(
+                egui::pos2(rect.left() + i as f32 * bar_width, bottom - height),
+                egui::pos2(rect.left() + (i + 1) as f32 * bar_width - 1.0, bottom),
+            );
+            painter.rect_filled(bar, 0.0, *color);
+            bottom -= height;
+        }
+    }
+}
+
+/// Draws an energy-versus-distance profile as a polyline scaled to fit
+fn draw_profile(ui: &mut egui::Ui, profile: &[(f32, f32)]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(256.0, 80.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(20));
+
+    let length = profile.last().map_or(0.0, |&(d, _)| d).max(1.0);
+    let peak = profile
+        .iter()
+        .fold(0.0f32, |max, &(_, e)| max.max(e))
+        .max(1.0);
+    let points: Vec<egui::Pos2> = profile
+        .iter()
+        .map(|&(d, e)| {
+            egui::pos2(
+                rect.left() + rect.width() * d / length,
+                rect.bottom() - rect.height() * e.max(0.0) / peak,
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY),
+    ));
 }
 
 impl eframe::App for MatrixApp {
+    #[cfg(feature = "persistence")]
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if let Ok(json) = serde_json::to_string(&self.settings()) {
+            storage.set_string(SETTINGS_KEY, json);
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
         use egui::Key;
 
         // ----------------------------
         // Input handling
         // ----------------------------
-        if ctx.input(|i| i.key_pressed(Key::ArrowRight))
+        // keys typed into a text field or drag value are meant for it, not the shortcuts
+        let typing = ctx.wants_keyboard_input();
+        let pressed = |key| !typing && ctx.input(|i| i.key_pressed(key));
+
+        if pressed(Key::ArrowRight)
             && !self.tracks_to_draw.is_empty()
             && self.current_mode == Mode::Single
         {
@@ -138,7 +1552,7 @@ impl eframe::App for MatrixApp {
             self.needs_update = true;
         }
 
-        if ctx.input(|i| i.key_pressed(Key::ArrowLeft))
+        if pressed(Key::ArrowLeft)
             && !self.tracks_to_draw.is_empty()
             && self.current_mode == Mode::Single
         {
@@ -150,11 +1564,78 @@ impl eframe::App for MatrixApp {
             self.needs_update = true;
         }
 
-        if ctx.input(|i| i.key_pressed(Key::M)) {
+        if self.current_mode == Mode::Single {
+            for (key, ty) in TYPE_KEYS {
+                if pressed(key)
+                    && let Some(next) = self.next_of_type(ty)
+                {
+                    self.current_track = next;
+                    self.needs_update = true;
+                }
+            }
+        }
+
+        // outside Single mode the arrows step through a loaded series instead
+        if self.current_mode != Mode::Single && self.series.is_some() {
+            if pressed(Key::ArrowRight) {
+                self.step_series(1);
+            }
+            if pressed(Key::ArrowLeft) {
+                self.step_series(-1);
+            }
+        }
+
+        if pressed(Key::M) {
             self.current_mode = self.current_mode.toggle();
             self.needs_update = true;
         }
 
+        // a focused text field keeps its own undo
+        let (undo, redo) = if typing {
+            (false, false)
+        } else {
+            ctx.input_mut(|i| {
+                (
+                    i.consume_key(egui::Modifiers::COMMAND, Key::Z),
+                    i.consume_key(egui::Modifiers::COMMAND, Key::Y),
+                )
+            })
+        };
+        let target = if undo {
+            self.history.undo()
+        } else if redo {
+            self.history.redo()
+        } else {
+            None
+        };
+        if let Some(state) = target {
+            self.restore(state);
+        }
+
+        self.poll_extraction();
+        if self.extraction.is_some() {
+            // keep polling while nothing else triggers a repaint
+            ctx.request_repaint();
+        }
+
+        let now = ctx.input(|i| i.time);
+        if let Some(series) = &self.series
+            && series.playing
+            && self.extraction.is_none()
+        {
+            // wait for each frame's particles before moving on
+            let wait = 1.0 / series.fps as f64 - (now - series.shown_at);
+            if wait <= 0.0 {
+                self.step_series(1);
+                if let Some(series) = &mut self.series {
+                    series.shown_at = now;
+                }
+                ctx.request_repaint();
+            } else {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(wait));
+            }
+        }
+
         if self.needs_update {
             self.update_image();
             self.needs_update = false;
@@ -169,7 +1650,12 @@ impl eframe::App for MatrixApp {
 
                 ui.separator();
 
-                if ui.button("◀ Prev").clicked() && self.current_mode == Mode::Single {
+                let can_step = !self.tracks_to_draw.is_empty() && self.current_mode == Mode::Single;
+
+                if ui
+                    .add_enabled(can_step, egui::Button::new("◀ Prev"))
+                    .clicked()
+                {
                     self.current_track = if self.current_track == 0 {
                         self.tracks_to_draw.len() - 1
                     } else {
@@ -178,7 +1664,10 @@ impl eframe::App for MatrixApp {
                     self.update_image();
                 }
 
-                if ui.button("Next ▶").clicked() && self.current_mode == Mode::Single {
+                if ui
+                    .add_enabled(can_step, egui::Button::new("Next ▶"))
+                    .clicked()
+                {
                     self.current_track = (self.current_track + 1) % self.tracks_to_draw.len();
                     self.update_image();
                 }
@@ -188,12 +1677,77 @@ impl eframe::App for MatrixApp {
                     self.update_image();
                 }
 
+                let order = self.track_order;
+                egui::ComboBox::from_id_source("track_order")
+                    .selected_text(format!("Order: {order:?}"))
+                    .show_ui(ui, |ui| {
+                        for order in TrackOrder::ALL {
+                            ui.selectable_value(&mut self.track_order, order, format!("{order:?}"));
+                        }
+                    });
+                if self.track_order != order {
+                    self.update_counter();
+                    self.update_image();
+                }
+
+                if ui
+                    .checkbox(&mut self.outline_only, "Outline only")
+                    .changed()
+                {
+                    self.update_image();
+                }
+
+                if ui
+                    .checkbox(&mut self.show_heatmap, "Show energy heatmap")
+                    .changed()
+                {
+                    self.update_image();
+                }
+
+                let colormap = self.colormap;
+                egui::ComboBox::from_id_source("colormap")
+                    .selected_text(format!("{colormap:?}"))
+                    .show_ui(ui, |ui| {
+                        for map in Colormap::ALL {
+                            ui.selectable_value(&mut self.colormap, map, format!("{map:?}"));
+                        }
+                    });
+                if self.colormap != colormap {
+                    self.update_image();
+                }
+
+                if ui
+                    .checkbox(&mut self.auto_contrast, "Auto contrast")
+                    .changed()
+                {
+                    self.update_image();
+                }
+
+                if ui
+                    .checkbox(&mut self.dim_low_confidence, "Dim uncertain")
+                    .changed()
+                {
+                    self.update_image();
+                }
+
+                if ui
+                    .add(egui::Slider::new(&mut self.scale, 1..=8).text("Scale"))
+                    .changed()
+                {
+                    self.needs_update = true;
+                    ui.ctx().request_repaint();
+                }
+
                 ui.separator();
 
                 ui.label(match self.current_mode {
                     Mode::Single => "Mode: Single Track",
                     Mode::Combined => "Mode: Combined",
                 });
+
+                if self.current_mode == Mode::Single {
+                    ui.weak("Jump: A alpha · B beta · G gamma · N muon · U unknown");
+                }
             });
         });
 
@@ -219,12 +1773,12 @@ impl eframe::App for MatrixApp {
 
                 for particle in &self.tracks_to_draw {
                     *count
-                        .get_mut(&particle.particle_type(&self.matrix))
+                        .get_mut(&self.classifier.classify(particle, &self.matrix))
                         .unwrap() += 1;
                 }
 
                 egui::Grid::new("stats_grid")
-                    .num_columns(2)
+                    .num_columns(3)
                     .spacing([10.0, 6.0])
                     .show(ui, |ui| {
                         for (label, ty) in [
@@ -234,42 +1788,566 @@ impl eframe::App for MatrixApp {
                             ("Muon", PartType::MUON),
                             ("Unknown", PartType::UNKNOWN),
                         ] {
+                            let (swatch, _) = ui
+                                .allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+                            ui.painter().rect_filled(swatch, 2.0, type_color(ty));
                             ui.label(label);
                             ui.label(count.get(&ty).unwrap().to_string());
                             ui.end_row();
                         }
                     });
 
-                let response_al = ui.checkbox(&mut self.show_alpha, "Alpha");
-                let response_be = ui.checkbox(&mut self.show_beta, "Beta");
-                let response_ga = ui.checkbox(&mut self.show_gamma, "Gamma");
-                let response_mu = ui.checkbox(&mut self.show_muon, "Muon");
-                let response_un = ui.checkbox(&mut self.show_unknown, "Unknown");
-
-                if response_al.changed()
-                    || response_be.changed()
-                    || response_ga.changed()
-                    || response_mu.changed()
-                    || response_un.changed()
+                let (all, none) = ui
+                    .horizontal(|ui| (ui.button("All").clicked(), ui.button("None").clicked()))
+                    .inner;
+
+                let solo_held = ui.input(|i| i.modifiers.shift);
+                let mut changed = false;
+                let mut solo = None;
+                for (show, label, ty) in [
+                    (&mut self.show_alpha, "Alpha", PartType::ALPHA),
+                    (&mut self.show_beta, "Beta", PartType::BETA),
+                    (&mut self.show_gamma, "Gamma", PartType::GAMMA),
+                    (&mut self.show_muon, "Muon", PartType::MUON),
+                    (&mut self.show_unknown, "Unknown", PartType::UNKNOWN),
+                ] {
+                    let response = ui
+                        .checkbox(show, label)
+                        .on_hover_text("Shift-click to show only this type");
+                    if response.clicked() && solo_held {
+                        solo = Some(ty);
+                    }
+                    changed |= response.changed();
+                }
+
+                if all {
+                    self.set_filters(|_| true);
+                } else if none {
+                    self.set_filters(|_| false);
+                } else if let Some(solo) = solo {
+                    self.set_filters(|ty| ty == solo);
+                } else if changed {
+                    self.update_counter();
+                    self.update_image();
+                }
+
+                if ui
+                    .checkbox(&mut self.hide_edge, "Hide edge tracks")
+                    .on_hover_text("Tracks touching the frame border are likely cut off")
+                    .changed()
                 {
                     self.update_counter();
                     self.update_image();
                 }
+
+                let (width, height) = frame_size(&self.matrix);
+                let mut cropped = self.roi.is_some();
+                let mut roi_changed = ui
+                    .checkbox(&mut cropped, "Region of interest")
+                    .on_hover_text("Draw only these cells, zoomed to the frame's size")
+                    .changed();
+                if roi_changed {
+                    // start from the middle quarter of the frame
+                    self.roi = cropped.then(|| Roi {
+                        x: width / 4,
+                        y: height / 4,
+                        width: (width / 2).max(1),
+                        height: (height / 2).max(1),
+                    });
+                }
+                if let Some(roi) = &mut self.roi {
+                    egui::Grid::new("roi").num_columns(4).show(ui, |ui| {
+                        for (label, value, max) in [
+                            ("x", &mut roi.x, width.saturating_sub(1)),
+                            ("y", &mut roi.y, height.saturating_sub(1)),
+                        ] {
+                            ui.label(label);
+                            roi_changed |= ui
+                                .add(egui::DragValue::new(value).clamp_range(0..=max))
+                                .changed();
+                        }
+                        ui.end_row();
+                        for (label, value, max) in
+                            [("w", &mut roi.width, width), ("h", &mut roi.height, height)]
+                        {
+                            ui.label(label);
+                            roi_changed |= ui
+                                .add(egui::DragValue::new(value).clamp_range(1..=max.max(1)))
+                                .changed();
+                        }
+                        ui.end_row();
+                    });
+                }
+                if roi_changed {
+                    self.update_image();
+                }
+
+                ui.separator();
+
+                let mut jump = None;
+                let mut sort_by = None;
+                if self.table_order.is_none() {
+                    self.table_order = Some(self.sorted_table());
+                }
+                ui.collapsing("Tracks", |ui| {
+                    let (sort, descending) = self.table_sort;
+                    let classifier = self.classifier.as_ref();
+                    let config = self.classifier_config();
+                    let order = self.table_order.as_deref().unwrap_or_default();
+                    // the header sits outside the scrolled rows, so both grids get the same
+                    // column widths to line up
+                    let column_width = 84.0;
+
+                    egui::Grid::new("track_table_header")
+                        .num_columns(TrackColumn::ALL.len())
+                        .min_col_width(column_width)
+                        .show(ui, |ui| {
+                            for column in TrackColumn::ALL {
+                                let arrow = match (column == sort, descending) {
+                                    (false, _) => "",
+                                    (true, false) => " ⬆",
+                                    (true, true) => " ⬇",
+                                };
+                                let header = format!("{column:?}{arrow}");
+                                if ui.selectable_label(column == sort, header).clicked() {
+                                    sort_by = Some(column);
+                                }
+                            }
+                            ui.end_row();
+                        });
+
+                    // only the rows scrolled into view are laid out
+                    let row_height = ui.spacing().interact_size.y;
+                    egui::ScrollArea::vertical()
+                        .id_source("track_table_scroll")
+                        .max_height(240.0)
+                        .show_rows(ui, row_height, order.len(), |ui, rows| {
+                            egui::Grid::new("track_table")
+                                .num_columns(TrackColumn::ALL.len())
+                                .min_col_width(column_width)
+                                .striped(true)
+                                .start_row(rows.start)
+                                .show(ui, |ui| {
+                                    for &i in &order[rows] {
+                                        let particle = &self.tracks_to_draw[i];
+                                        let selected = self.current_mode == Mode::Single
+                                            && i == self.current_track;
+                                        if ui
+                                            .selectable_label(selected, (i + 1).to_string())
+                                            .clicked()
+                                        {
+                                            jump = Some(i);
+                                        }
+                                        ui.label(particle.size().to_string());
+                                        ui.label(format!(
+                                            "{:.1}",
+                                            particle.total_energy(&self.matrix)
+                                        ));
+                                        ui.label(format!(
+                                            "{:.2}",
+                                            particle.roundness_with(&config)
+                                        ));
+                                        ui.label(format!("{:.2}", particle.winding()));
+                                        ui.label(type_label(particle, &self.matrix, classifier));
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                });
+                if let Some(column) = sort_by {
+                    // clicking the sorted column again flips the direction
+                    self.table_sort = (column, column == self.table_sort.0 && !self.table_sort.1);
+                    self.table_order = None;
+                }
+                if let Some(i) = jump {
+                    self.current_mode = Mode::Single;
+                    self.current_track = i;
+                    self.update_image();
+                }
+
+                ui.collapsing("Frame statistics", |ui| {
+                    let mut stats = crate::stats::FrameStats::compute(
+                        &self.matrix,
+                        &self.all_tracks,
+                        self.classifier.as_ref(),
+                    );
+                    let saturation_level = self.classifier_config().saturation_level;
+                    if saturation_level > 0.0 {
+                        stats = stats.with_saturation(&self.matrix, saturation_level);
+                    }
+                    ui.label(format!("Total energy: {:.2}", stats.total_energy));
+                    ui.label(format!(
+                        "Lit pixels: {} ({:.2}%)",
+                        stats.lit_pixels,
+                        100.0 * stats.occupancy()
+                    ));
+                    if stats.saturated_pixels > 0 {
+                        ui.label(format!("Saturated pixels: {}", stats.saturated_pixels));
+                    }
+                });
+
+                if let Some(second) = &self.second {
+                    ui.collapsing("Coincidences", |ui| {
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut self.coincidence_tolerance, 0.5..=10.0)
+                                    .text("tolerance (px)"),
+                            )
+                            .changed()
+                        {
+                            // the second frame highlights the current track's partner
+                            self.needs_update = true;
+                        }
+                        let pairs = crate::decoder::match_coincidences(
+                            &self.tracks_to_draw,
+                            &second.tracks_to_draw,
+                            self.coincidence_tolerance,
+                        );
+                        ui.label(format!("{} tracks in both frames", pairs.len()));
+                        for (a, b) in pairs {
+                            ui.label(format!("#{} ↔ #{}", a + 1, b + 1));
+                        }
+                    });
+                }
+
+                ui.collapsing("Size histogram", |ui| {
+                    ui.add(egui::Slider::new(&mut self.size_bins, 1..=50).text("bins"));
+
+                    let sizes: Vec<f32> = self
+                        .tracks_to_draw
+                        .iter()
+                        .map(|p| p.size() as f32)
+                        .collect();
+                    let (counts, min, max) = histogram(&sizes, self.size_bins);
+                    draw_histogram(ui, &[(counts, egui::Color32::LIGHT_GRAY)]);
+                    ui.label(format!("{min:.0} – {max:.0} px"));
+                });
+
+                ui.collapsing("Energy spectrum", |ui| {
+                    ui.add(egui::Slider::new(&mut self.energy_bins, 1..=50).text("bins"));
+
+                    let energies: Vec<(PartType, f32)> = self
+                        .tracks_to_draw
+                        .iter()
+                        .map(|p| {
+                            (
+                                self.classifier.classify(p, &self.matrix),
+                                p.total_energy(&self.matrix),
+                            )
+                        })
+                        .collect();
+                    let all: Vec<f32> = energies.iter().map(|&(_, e)| e).collect();
+                    let (_, min, max) = histogram(&all, self.energy_bins);
+                    let series: Vec<(Vec<usize>, egui::Color32)> = TYPE_PALETTE
+                        .iter()
+                        .map(|&(ty, color)| {
+                            let values: Vec<f32> = energies
+                                .iter()
+                                .filter(|&&(t, _)| t == ty)
+                                .map(|&(_, e)| e)
+                                .collect();
+                            (histogram_in(&values, self.energy_bins, min, max), color)
+                        })
+                        .collect();
+                    draw_histogram(ui, &series);
+                    ui.label(format!("{min:.0} – {max:.0} total energy"));
+                });
+            });
+
+        // ============================
+        // BOTTOM BAR
+        // ============================
+        egui::TopBottomPanel::bottom("bottom_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("📂 Open File").clicked()
+                    && let Some(path) = FileDialog::new().pick_file()
+                {
+                    self.open(path, false);
+                }
+
+                if ui.button("📂 Open Second File").clicked()
+                    && let Some(path) = FileDialog::new().pick_file()
+                {
+                    self.open(path, true);
+                }
+
+                if ui
+                    .button("📂 Open Folder")
+                    .on_hover_text("Load a directory of frames as a time series")
+                    .clicked()
+                    && let Some(dir) = FileDialog::new().pick_folder()
+                {
+                    self.open_series(&dir);
+                }
+
+                if self.second.is_some() && ui.button("✖ Close Second").clicked() {
+                    self.second = None;
+                }
+
+                if ui
+                    .button("📂 Background")
+                    .on_hover_text("Load a dark frame to subtract from every frame")
+                    .clicked()
+                    && let Some(path) = FileDialog::new().pick_file()
+                {
+                    self.open_background(&path);
+                }
+
+                if self.background.is_some() && ui.button("✖ Clear Background").clicked() {
+                    self.log("Background cleared");
+                    self.set_background(None);
+                }
+
+                ui.separator();
+
+                let range = ui.add(egui::Slider::new(&mut self.extract.range, 1..=5).text("Range"));
+                ui.label("Threshold");
+                let threshold = ui.add(
+                    egui::DragValue::new(&mut self.extract.threshold)
+                        .speed(0.5)
+                        .clamp_range(0.0..=f32::MAX),
+                );
+                self.extraction_pending |= range.changed() || threshold.changed();
+                let editing = [&range, &threshold]
+                    .iter()
+                    .any(|response| response.dragged() || response.has_focus());
+                if self.extraction_pending && !editing {
+                    self.extraction_pending = false;
+                    self.reextract(false);
+                }
+
+                ui.label("Gain");
+                let gain = ui.add(
+                    egui::DragValue::new(&mut self.extract.calibration.gain)
+                        .speed(0.01)
+                        .clamp_range(0.0..=f32::MAX),
+                );
+                ui.label("Offset");
+                let offset =
+                    ui.add(egui::DragValue::new(&mut self.extract.calibration.offset).speed(0.1));
+                // every drag step would re-run preprocessing and extraction
+                self.calibration_pending |= gain.changed() || offset.changed();
+                let editing = [&gain, &offset]
+                    .iter()
+                    .any(|response| response.dragged() || response.has_focus());
+                if self.calibration_pending && !editing {
+                    self.calibration_pending = false;
+                    self.recalibrate();
+                }
+
+                let mut merging = self.extract.gamma_merge_radius > 0;
+                let toggled = ui
+                    .checkbox(&mut merging, "Merge GAMMA hits")
+                    .on_hover_text("Join charge-sharing fragments into one particle")
+                    .changed();
+                let radius = ui.add_enabled(
+                    merging,
+                    egui::Slider::new(&mut self.gamma_radius, 1..=10).text("Merge radius"),
+                );
+                if toggled || radius.changed() {
+                    self.extract.gamma_merge_radius = if merging { self.gamma_radius } else { 0 };
+                    self.merge();
+                }
+                if merging {
+                    ui.label(format!("{} particles merged", self.merged_count()));
+                }
+
+                let idle = self.extraction.is_none();
+                if ui
+                    .add_enabled(idle && self.has_frame(), egui::Button::new("🔄 Re-extract"))
+                    .clicked()
+                {
+                    self.reextract(true);
+                }
+
+                if !idle {
+                    ui.spinner();
+                    ui.label("extracting...");
+                }
+
+                ui.separator();
+
+                if ui
+                    .add_enabled(idle, egui::Button::new("💾 Save CSV"))
+                    .clicked()
+                    && let Some(path) = FileDialog::new()
+                        .add_filter("CSV", &["csv"])
+                        .set_file_name("particles.csv")
+                        .save_file()
+                {
+                    match crate::export::export_csv(
+                        &path,
+                        &self.all_tracks,
+                        &self.matrix,
+                        self.classifier.as_ref(),
+                    ) {
+                        Ok(()) => self.log(format!("Saved CSV to {}", path.display())),
+                        Err(e) => self.log(format!("Failed to save {}: {e}", path.display())),
+                    }
+                }
+
+                if ui
+                    .add_enabled(idle && self.has_frame(), egui::Button::new("🖼 Save PNG"))
+                    .clicked()
+                    && let Some(path) = FileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .set_file_name("frame.png")
+                        .save_file()
+                {
+                    match save_png(&path, &self.image) {
+                        Ok(()) => self.log(format!("Saved PNG to {}", path.display())),
+                        Err(e) => {
+                            self.error = Some(format!("Failed to save {}: {e}", path.display()))
+                        }
+                    }
+                }
+            });
+        });
+
+        // ============================
+        // PLAYBACK
+        // ============================
+        if let Some(series) = &mut self.series {
+            let mut target = None;
+            let mut close = false;
+            egui::TopBottomPanel::bottom("playback").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let len = series.frames.len();
+                    if ui.button("⏮").clicked() {
+                        target = Some((series.index + len - 1) % len);
+                    }
+                    let play = if series.playing { "⏸" } else { "▶" };
+                    if ui.button(play).clicked() {
+                        series.playing = !series.playing;
+                        series.shown_at = now;
+                    }
+                    if ui.button("⏭").clicked() {
+                        target = Some((series.index + 1) % len);
+                    }
+
+                    let mut shown = series.index + 1;
+                    if ui
+                        .add(egui::Slider::new(&mut shown, 1..=len).text("Frame"))
+                        .changed()
+                    {
+                        target = Some(shown - 1);
+                    }
+                    ui.add(
+                        egui::DragValue::new(&mut series.fps)
+                            .speed(0.1)
+                            .clamp_range(0.5..=30.0)
+                            .suffix(" fps"),
+                    );
+                    if let Some(name) = series.frames[series.index].path.file_name() {
+                        ui.label(name.to_string_lossy());
+                    }
+                    if !series.playing && self.current_mode != Mode::Single {
+                        ui.weak("◀/▶ to step");
+                    }
+                    close = ui.button("✖ Close Series").clicked();
+                });
             });
+            if close {
+                self.series = None;
+            } else if let Some(index) = target {
+                self.show_series_frame(index);
+            }
+        }
+
+        // ============================
+        // ANALYSIS LOG
+        // ============================
+        egui::TopBottomPanel::bottom("log_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new(format!("Analysis log ({})", self.log.len()))
+                .id_source("analysis_log")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(120.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for line in &self.log {
+                                ui.monospace(line);
+                            }
+                        });
+                });
+        });
 
         // ============================
         // CENTER VIEW
         // ============================
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
-                let texture =
-                    ui.ctx()
-                        .load_texture("track_image", self.image.clone(), Default::default());
+                // nearest filtering keeps cells crisp when a region of interest is zoomed
+                let zoom = self.zoom();
+                let show = |ui: &mut egui::Ui, name: &str, image: &ColorImage| {
+                    let texture =
+                        ui.ctx()
+                            .load_texture(name, image.clone(), egui::TextureOptions::NEAREST);
+                    let size = egui::vec2(image.size[0] as f32, image.size[1] as f32) * zoom;
+                    ui.add(egui::Image::new(&texture).fit_to_exact_size(size))
+                };
 
-                ui.image(&texture);
+                let response = match &self.second {
+                    Some(second) => {
+                        ui.horizontal(|ui| {
+                            let response = show(ui, "track_image", &self.image);
+                            ui.separator();
+                            show(ui, "second_track_image", &second.image);
+                            response
+                        })
+                        .inner
+                    }
+                    None => show(ui, "track_image", &self.image),
+                };
+                let (width, height) = frame_size(&self.matrix);
+                if let Some(arrow) = self.travel_arrow() {
+                    let region = view_region(self.roi, width, height);
+                    let cell_size = self.scale as f32 * zoom;
+                    let [start, tip] = arrow.map(|(x, y)| {
+                        // off-region ends are clipped against the image
+                        let x = x as f32 - region.x as f32 + 0.5;
+                        let y = y as f32 - region.y as f32 + 0.5;
+                        response.rect.min + egui::vec2(x, y) * cell_size
+                    });
+                    ui.painter_at(response.rect).arrow(
+                        start,
+                        tip - start,
+                        egui::Stroke::new(2.0, egui::Color32::WHITE),
+                    );
+                }
+                if let Some(pos) = response.hover_pos()
+                    && width > 0
+                    && let Some(index) = hovered_track(
+                        pos,
+                        response.rect,
+                        self.scale as f32 * zoom,
+                        view_region(self.roi, width, height),
+                        width,
+                        &self.pixel_owner,
+                    )
+                {
+                    let particle = &self.tracks_to_draw[index];
+                    response.on_hover_ui_at_pointer(|ui| {
+                        ui.label(format!("Track {}", index + 1));
+                        ui.label(format!("Size: {}", particle.size()));
+                        ui.label(format!(
+                            "Energy: {:.1}",
+                            particle.total_energy(&self.matrix)
+                        ));
+                        ui.label(format!(
+                            "Type: {}",
+                            type_label(particle, &self.matrix, self.classifier.as_ref())
+                        ));
+                    });
+                }
 
                 ui.add_space(8.0);
 
+                if self.tracks_to_draw.is_empty() {
+                    ui.label("No particles detected");
+                    return;
+                }
+
                 ui.label(format!(
                     "Track {}/{}",
                     self.current_track + 1,
@@ -277,43 +2355,77 @@ impl eframe::App for MatrixApp {
                 ));
 
                 if self.current_mode == Mode::Single {
+                    let particle = &self.tracks_to_draw[self.current_track];
+                    ui.label(format!(
+                        "Particle: {} (confidence {:.2})",
+                        type_label(particle, &self.matrix, self.classifier.as_ref()),
+                        self.classifier.confidence(particle, &self.matrix)
+                    ));
+
+                    let ((min_x, min_y), (max_x, max_y)) = particle.bounding_box();
                     ui.label(format!(
-                        "Particle: {:?}",
-                        self.tracks_to_draw[self.current_track].particle_type(&self.matrix)
+                        "Box: ({min_x}, {min_y}) – ({max_x}, {max_y}), aspect {:.2}",
+                        particle.aspect_ratio()
                     ));
+
+                    let winding = particle.signed_winding();
+                    let sense = match winding {
+                        w if w > 0.0 => " clockwise",
+                        w if w < 0.0 => " counterclockwise",
+                        _ => "",
+                    };
+                    ui.label(format!("Winding: {:.2} turns{sense}", winding.abs()))
+                        .on_hover_text("Sense of turning walking inwards along the track");
+
+                    ui.label("Energy profile");
+                    draw_profile(ui, &particle.energy_profile(&self.matrix));
                 }
             });
         });
 
         // ============================
-        // BOTTOM BAR
+        // BINARY DIMENSIONS PROMPT
         // ============================
-        egui::TopBottomPanel::bottom("bottom_bar").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                if ui.button("📂 Open File").clicked()
-                    && let Some(path) = FileDialog::new().pick_file()
-                {
-                    if let Ok(mat) = crate::read_lines(path) {
-                        self.matrix = mat;
-                        let mut id_map = vec![vec![0; crate::SIZE]; crate::SIZE];
-                        self.all_tracks =
-                            crate::particle_extractor::extract(&self.matrix, &mut id_map, 1)
-                                .values()
-                                .map(|t| crate::decoder::Particle::new(t.clone()))
-                                .collect();
-                        self.update_counter();
-                        self.update_image();
-                    } else {
-                        self.error = Some("error".to_string());
-                    }
-                }
-            });
-        });
+        let mut load = false;
+        let mut cancel = false;
+        if let Some(prompt) = &mut self.binary_prompt {
+            egui::Window::new("Binary frame")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "The shape of {} can't be inferred.",
+                        prompt.path.display()
+                    ));
+                    ui.horizontal(|ui| {
+                        ui.label("Width");
+                        ui.add(egui::DragValue::new(&mut prompt.width).clamp_range(1..=65536));
+                        ui.label("Height");
+                        ui.add(egui::DragValue::new(&mut prompt.height).clamp_range(1..=65536));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut prompt.dtype, BinaryType::U16, "u16");
+                        ui.radio_value(&mut prompt.dtype, BinaryType::F32, "f32");
+                    });
+                    ui.horizontal(|ui| {
+                        load = ui.button("Load").clicked();
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+        }
+        if load && let Some(prompt) = self.binary_prompt.take() {
+            let result =
+                frame::read_binary(&prompt.path, prompt.width, prompt.height, prompt.dtype);
+            self.show_frame(&prompt.path, result, prompt.second);
+        } else if cancel {
+            self.binary_prompt = None;
+        }
 
         // ============================
         // ERROR POPUP
         // ============================
-        if self.error.is_some() {
+        if let Some(message) = self.error.clone() {
             egui::Window::new("Error")
                 .collapsible(false)
                 .resizable(false)
@@ -325,12 +2437,346 @@ impl eframe::App for MatrixApp {
                 )
                 .show(ctx, |ui| {
                     ui.heading("⚠ Error");
-                    ui.label("File was incorrectly formatted.");
+                    ui.label(message);
                     ui.add_space(10.0);
                     if ui.button("OK").clicked() {
                         self.error = None;
                     }
                 });
         }
+
+        // whatever this frame changed becomes one undo step
+        self.history.record(self.view_state());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults_and_overrides() {
+        let mut grid = vec![vec![0.0f32; 16]; 16];
+        grid[4][4] = 30.0;
+        grid[10][2..12].fill(30.0);
+
+        let app = MatrixApp::new(MatrixAppBuilder::new(grid.clone()));
+        assert_eq!(app.all_tracks.len(), 2);
+        assert_eq!((app.scale, app.current_mode), (2, Mode::Combined));
+        assert_eq!(app.colormap, Colormap::Jet);
+
+        let app = MatrixAppBuilder::new(grid)
+            .tracks(Vec::new())
+            .scale(20)
+            .colormap(Colormap::Viridis)
+            .mode(Mode::Single)
+            .classifier_config(ClassifierConfig {
+                min_short_size: 1,
+                ..ClassifierConfig::default()
+            })
+            .build();
+        assert!(app.all_tracks.is_empty());
+        assert_eq!((app.scale, app.current_mode), (8, Mode::Single));
+        assert_eq!(app.colormap, Colormap::Viridis);
+        assert_eq!(app.history.current.settings.mode, Mode::Single);
+    }
+
+    #[test]
+    fn test_merge_without_reextracting() {
+        // two charge-shared photon hits split by a one-pixel gap each, and one lone hit
+        let mut grid = vec![vec![0.0f32; 64]; 64];
+        for (x, y) in [(10, 10), (12, 10), (30, 30), (30, 32), (50, 50)] {
+            grid[y][x] = 30.0;
+        }
+        let mut app = MatrixAppBuilder::new(grid).build();
+        assert_eq!((app.all_tracks.len(), app.merged_count()), (5, 0));
+
+        app.extract.gamma_merge_radius = 2;
+        app.merge();
+        assert!(app.extraction.is_none());
+        assert_eq!((app.all_tracks.len(), app.merged_count()), (3, 2));
+        assert_eq!(app.tracks_to_draw.len(), 3);
+
+        app.extract.gamma_merge_radius = 0;
+        app.merge();
+        assert_eq!(app.all_tracks.len(), 5);
+
+        // tracks handed to the builder are merged by it, and can still be unmerged
+        let extract = ExtractConfig {
+            gamma_merge_radius: 2,
+            ..ExtractConfig::default()
+        };
+        let extracted = particle_extractor::extract_particles(&app.matrix, &extract).unwrap();
+        let mut app = MatrixAppBuilder::new(app.raw.clone())
+            .tracks(extracted)
+            .extract(extract)
+            .build();
+        assert_eq!((app.all_tracks.len(), app.merged_count()), (3, 2));
+        app.extract.gamma_merge_radius = 0;
+        app.merge();
+        assert_eq!((app.all_tracks.len(), app.merged_count()), (5, 0));
+    }
+
+    #[test]
+    fn test_restore_clamps_without_recording() {
+        let mut grid = vec![vec![0.0f32; 16]; 16];
+        grid[1][1] = 30.0;
+        grid[5][5] = 30.0;
+        grid[12][12] = 30.0;
+        let mut app = MatrixAppBuilder::new(grid).build();
+        app.current_track = 2;
+        app.history.record(app.view_state());
+        app.current_track = 0;
+        app.history.record(app.view_state());
+
+        // the tracks changed under the history, so the old selection no longer exists
+        app.all_tracks.truncate(1);
+        let state = app.history.undo().unwrap();
+        app.restore(state);
+        assert_eq!(app.current_track, 0);
+        app.history.record(app.view_state());
+        assert_eq!(app.history.redo.len(), 1);
+    }
+
+    #[test]
+    fn test_second_frame_selection() {
+        let mut grid = vec![vec![0.0f32; 32]; 32];
+        grid[2][2..6].fill(30.0);
+        grid[20][20..24].fill(30.0);
+        // the second plane only saw the lower track, plus a hit before it in scan order
+        let mut back = vec![vec![0.0f32; 32]; 32];
+        back[10][28] = 30.0;
+        back[21][20..24].fill(30.0);
+
+        let mut app = MatrixAppBuilder::new(grid).build();
+        let tracks = particle_extractor::extract_particles(&back, &app.extract).unwrap();
+        app.second = Some(SecondFrame {
+            raw: back.clone(),
+            matrix: back,
+            extracted: tracks.clone(),
+            all_tracks: tracks,
+            tracks_to_draw: Vec::new(),
+            image: ColorImage::new([1, 1], egui::Color32::BLACK),
+        });
+        app.update_counter();
+
+        app.current_track = 0;
+        assert_eq!(app.second_selection(), None);
+        app.current_track = 1;
+        assert_eq!(app.second_selection(), Some(1));
+    }
+
+    #[test]
+    fn test_table_order_cached() {
+        let mut grid = vec![vec![0.0f32; 16]; 16];
+        grid[1][1] = 30.0;
+        grid[5][2..10].fill(20.0);
+        grid[12][3..6].fill(200.0);
+
+        let mut app = MatrixAppBuilder::new(grid).build();
+        app.table_sort = (TrackColumn::Energy, true);
+        app.table_order = Some(app.sorted_table());
+        assert_eq!(app.table_order.as_deref(), Some(&[2, 1, 0][..]));
+
+        // the drawn tracks change, so the order is rebuilt on the next repaint
+        app.show_unknown = false;
+        app.update_counter();
+        assert_eq!(app.table_order, None);
+    }
+
+    #[test]
+    fn test_background_drops_series_tracks() {
+        let mut grid = vec![vec![0.0f32; 16]; 16];
+        grid[4][4] = 30.0;
+        let mut app = MatrixAppBuilder::new(grid.clone()).build();
+        let frame = |name: &str| Frame {
+            path: PathBuf::from(name),
+            grid: grid.clone(),
+        };
+        app.series = Some(Series {
+            frames: vec![frame("a.txt"), frame("b.txt")],
+            index: 0,
+            tracks: vec![Some(app.extracted.clone()), Some(app.extracted.clone())],
+            config: app.extract,
+            playing: false,
+            fps: 2.0,
+            shown_at: 0.0,
+        });
+
+        // the dark frame cancels the hit, so nothing cached before it may be shown again
+        app.set_background(Some(grid.clone()));
+        let series = app.series.as_ref().unwrap();
+        assert!(series.tracks.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_track_order() {
+        let mut grid = vec![vec![0.0f32; 16]; 16];
+        grid[1][1] = 30.0;
+        grid[5][2..10].fill(20.0);
+        grid[12][3..6].fill(200.0);
+
+        let mut app = MatrixAppBuilder::new(grid).build();
+        app.current_track = 1;
+        let selected = app.tracks_to_draw[1].get_track();
+
+        app.track_order = TrackOrder::Energy;
+        app.update_counter();
+        let energies: Vec<f32> = app
+            .tracks_to_draw
+            .iter()
+            .map(|p| p.total_energy(&app.matrix))
+            .collect();
+        assert_eq!(energies, vec![600.0, 160.0, 30.0]);
+        // the selection follows the track to its new place
+        assert_eq!(app.tracks_to_draw[app.current_track].get_track(), selected);
+
+        app.track_order = TrackOrder::Size;
+        app.update_counter();
+        assert_eq!(app.tracks_to_draw[0].size(), 8);
+    }
+
+    #[test]
+    fn test_colormap_endpoints() {
+        let rgb = |r, g, b| egui::Color32::from_rgb(r, g, b);
+        for (map, low, high) in [
+            (Colormap::Grayscale, rgb(0, 0, 0), rgb(255, 255, 255)),
+            (Colormap::Viridis, rgb(68, 1, 84), rgb(253, 231, 37)),
+            (Colormap::Inferno, rgb(0, 0, 4), rgb(252, 255, 164)),
+            (Colormap::Jet, rgb(0, 0, 128), rgb(128, 0, 0)),
+        ] {
+            assert_eq!(map.color(0.0), low, "{map:?}");
+            assert_eq!(map.color(1.0), high, "{map:?}");
+            // out-of-range values clamp to the ends
+            assert_eq!(map.color(-3.0), low, "{map:?}");
+            assert_eq!(map.color(7.0), high, "{map:?}");
+        }
+        assert_eq!(Colormap::Grayscale.color(0.5), rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn test_percentile_range_ignores_outlier() {
+        let mut matrix = vec![vec![0.0f32; 20]; 10];
+        for (i, cell) in matrix.iter_mut().flatten().take(99).enumerate() {
+            *cell = (i + 1) as f32;
+        }
+        matrix[9][19] = 1.0e6;
+        let lit: Vec<f32> = matrix
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|&v| v > 0.0)
+            .collect();
+
+        let (low, high) = percentile_range(&lit, 0.01, 0.99);
+        assert_eq!(high, 99.0);
+        assert!(low <= 2.0);
+        assert_eq!(percentile_range(&[], 0.01, 0.99), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_overlap_priority() {
+        // a faint line and a bright blob sharing the pixel (5, 2)
+        let mut matrix = vec![vec![0.0f32; 12]; 6];
+        matrix[2][0..12].fill(1.0);
+        for row in &mut matrix[2..4] {
+            row[5..7].fill(60.0);
+        }
+        let line = Particle::new((0..12).map(|x| (x, 2)).collect());
+        let blob = Particle::new(vec![(5, 2), (6, 2), (5, 3), (6, 3)]);
+        let classifier = HeuristicClassifier::default();
+        let blob_color = type_color(classifier.classify(&blob, &matrix));
+        assert_ne!(blob_color, type_color(classifier.classify(&line, &matrix)));
+
+        let options = RenderOptions {
+            mode: Mode::Combined,
+            scale: 1,
+            current: 0,
+            outline_only: false,
+            show_heatmap: false,
+            colormap: Colormap::default(),
+            auto_contrast: false,
+            dim_low_confidence: false,
+            roi: None,
+        };
+        for tracks in [
+            vec![line.clone(), blob.clone()],
+            vec![blob.clone(), line.clone()],
+        ] {
+            let image = render_tracks(&matrix, &tracks, &classifier, &options);
+            assert_eq!(image.pixels[2 * 12 + 5], blob_color);
+        }
+    }
+
+    #[test]
+    fn test_render_single_pixel_track() {
+        let mut matrix = vec![vec![0.0f32; 4]; 3];
+        matrix[1][2] = 10.0;
+        let tracks = vec![Particle::new(vec![(2, 1)])];
+        let options = RenderOptions {
+            mode: Mode::Single,
+            scale: 2,
+            current: 0,
+            outline_only: false,
+            show_heatmap: false,
+            colormap: Colormap::default(),
+            auto_contrast: false,
+            dim_low_confidence: false,
+            roi: None,
+        };
+
+        let image = render_tracks(&matrix, &tracks, &HeuristicClassifier::default(), &options);
+        assert_eq!(image.size, [8, 6]);
+
+        let color = type_color(PartType::GAMMA);
+        let lit: Vec<usize> = (0..image.pixels.len())
+            .filter(|&i| image.pixels[i] != egui::Color32::BLACK)
+            .collect();
+        assert_eq!(lit, vec![2 * 8 + 4, 2 * 8 + 5, 3 * 8 + 4, 3 * 8 + 5]);
+        assert!(lit.iter().all(|&i| image.pixels[i] == color));
+    }
+
+    #[test]
+    fn test_render_roi_crops() {
+        let mut matrix = vec![vec![0.0f32; 20]; 20];
+        let inside = Particle::new(vec![(5, 5), (6, 5)]);
+        let outside = Particle::new(vec![(15, 15)]);
+        // runs off the right edge of the region
+        let clipped = Particle::new((6..12).map(|x| (x, 8)).collect());
+        for &(x, y) in [&inside, &outside, &clipped]
+            .iter()
+            .flat_map(|p| p.track_ref())
+        {
+            matrix[y][x] = 10.0;
+        }
+        let options = RenderOptions {
+            mode: Mode::Combined,
+            scale: 1,
+            current: 0,
+            outline_only: false,
+            show_heatmap: false,
+            colormap: Colormap::default(),
+            auto_contrast: false,
+            dim_low_confidence: false,
+            roi: Some(Roi {
+                x: 4,
+                y: 4,
+                width: 6,
+                height: 6,
+            }),
+        };
+
+        let tracks = [inside, outside, clipped];
+        let image = render_tracks(&matrix, &tracks, &HeuristicClassifier::default(), &options);
+        assert_eq!(image.size, [6, 6]);
+
+        let lit: Vec<usize> = (0..image.pixels.len())
+            .filter(|&i| image.pixels[i] != egui::Color32::BLACK)
+            .collect();
+        // the inside track at (1, 1) and (2, 1), the clipped one from x 2 to 5 on row 4
+        assert_eq!(
+            lit,
+            vec![6 + 1, 6 + 2, 4 * 6 + 2, 4 * 6 + 3, 4 * 6 + 4, 4 * 6 + 5]
+        );
     }
 }
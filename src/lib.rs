@@ -0,0 +1,35 @@
+//! Extraction and classification of particle tracks in detector frames.
+//!
+//! A frame is a row-major grid of deposited energies. [`particle_extractor::extract`] groups
+//! its lit cells into tracks, and each track wrapped in a [`decoder::Particle`] can be
+//! classified:
+//!
+//! ```
+//! use muon_decoder::decoder::{PartType, Particle};
+//! use muon_decoder::particle_extractor::{extract, ExtractConfig};
+//!
+//! let mut grid = vec![vec![0.0f32; 16]; 16];
+//! grid[4][4] = 30.0;
+//!
+//! let tracks = extract(&grid, &ExtractConfig::default())?;
+//! assert_eq!(tracks.len(), 1);
+//!
+//! let particle = Particle::new(tracks[&1].clone());
+//! assert_eq!(particle.particle_type(&grid), PartType::GAMMA);
+//! # Ok::<(), muon_decoder::particle_extractor::ExtractError>(())
+//! ```
+//!
+//! Frames are read with the helpers in [`frame`], and [`classifier::Classifier`] is the
+//! extension point for replacing the built-in heuristics. With the default `gui` feature,
+//! `graphics::MatrixApp` is the viewer the `muon_decoder` binary opens.
+
+pub mod batch;
+pub mod classifier;
+pub mod decoder;
+pub mod export;
+pub mod frame;
+#[cfg(feature = "gui")]
+pub mod graphics;
+pub mod particle_extractor;
+pub mod preprocess;
+pub mod stats;
@@ -1,47 +1,118 @@
-mod decoder;
-mod graphics;
-mod particle_extractor;
+mod cli;
 
-use std::fs::File;
-use std::io::{self, BufRead, Error};
-use std::path::Path;
+use muon_decoder::classifier::HeuristicClassifier;
+use muon_decoder::decoder::ClassifierConfig;
+use muon_decoder::graphics::MatrixAppBuilder;
+use muon_decoder::{batch, frame, particle_extractor, preprocess, stats};
+use std::process;
+
+/// Side of the blank frame the viewer opens with when no input is given
 const SIZE: usize = 256;
 
+/// Writes the JSON report of the frame, exiting on failure
+#[cfg(feature = "serde")]
+fn write_report(
+    path: &std::path::Path,
+    tracks: &[muon_decoder::decoder::Particle],
+    grid: &[Vec<f32>],
+    classifier: &HeuristicClassifier,
+) {
+    if let Err(e) = muon_decoder::export::write_report(path, tracks, grid, classifier) {
+        eprintln!("error: failed to write {}: {e}", path.display());
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_report(
+    _path: &std::path::Path,
+    _tracks: &[muon_decoder::decoder::Particle],
+    _grid: &[Vec<f32>],
+    _classifier: &HeuristicClassifier,
+) {
+    eprintln!("error: --report needs the `serde` feature");
+    process::exit(1);
+}
+
 fn main() -> eframe::Result<()> {
-    let grid: Vec<Vec<f32>> = vec![vec![0.0; SIZE]; SIZE];
+    let args = cli::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{}", cli::USAGE);
+        process::exit(2);
+    });
+
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let classifier = HeuristicClassifier {
+        config: ClassifierConfig {
+            saturation_level: args.saturation.unwrap_or(0.0),
+            ..ClassifierConfig::default()
+        },
+    };
+
+    if let Some(dir) = &args.batch {
+        match batch::process_directory(dir, &args.extract, &classifier) {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => {
+                eprintln!("error: failed to read {}: {e}", dir.display());
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    let tracks: Vec<decoder::Particle> = Vec::new();
+    let raw = match &args.input {
+        Some(path) => {
+            frame::read_frame(path, frame::FrameFormat::from_path(path)).unwrap_or_else(|e| {
+                eprintln!("error: failed to read {}: {e}", path.display());
+                process::exit(1);
+            })
+        }
+        None => vec![vec![0.0; SIZE]; SIZE],
+    };
+    let grid = preprocess::prepare(raw.clone(), &args.extract);
+    let (tracks, labelling) = particle_extractor::extract_with_stats(&grid, &args.extract)
+        .unwrap_or_else(|e| {
+            eprintln!("error: failed to extract particles: {e}");
+            process::exit(1);
+        });
+    let extracted = particle_extractor::into_particles(tracks);
+
+    if args.headless {
+        let tracks = particle_extractor::merge_gammas(
+            extracted,
+            &grid,
+            &classifier,
+            args.extract.gamma_merge_radius,
+        );
+        let mut stats = stats::FrameStats::compute(&grid, &tracks, &classifier);
+        if let Some(max_value) = args.saturation {
+            stats = stats.with_saturation(&grid, max_value);
+        }
+        println!("{stats}");
+        println!("{labelling}");
+        if let Some(path) = &args.report {
+            write_report(path, &tracks, &grid, &classifier);
+        }
+        return Ok(());
+    }
 
     // graphics
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "256x256 Matrix Viewer",
         options,
-        Box::new(move |_cc| Box::new(graphics::MatrixApp::new(grid, tracks, 2))),
+        Box::new(move |cc| {
+            Box::new(
+                MatrixAppBuilder::new(raw)
+                    .tracks(extracted)
+                    .classifier(Box::new(classifier))
+                    .extract(args.extract)
+                    .storage(cc.storage)
+                    .build(),
+            )
+        }),
     )
 }
-
-pub fn read_lines<P>(filename: P) -> Result<Vec<Vec<f32>>, std::io::Error>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    let lines = io::BufReader::new(file).lines();
-
-    let mut grid: Vec<Vec<f32>> = Vec::with_capacity(SIZE);
-
-    for line_result in lines {
-        let line = line_result?;
-        let row: Vec<f32> = line
-            .split_whitespace()
-            .map(|val| {
-                val.parse::<f32>()
-                    .map_err(|e| Error::new(io::ErrorKind::InvalidData, e.to_string()))
-            })
-            .collect::<Result<Vec<f32>, _>>()?;
-
-        grid.push(row);
-    }
-
-    Ok(grid)
-}
@@ -1,59 +1,695 @@
+use crate::classifier::Classifier;
+use crate::decoder::{PartType, Particle};
+use crate::frame::{ShapeError, check_shape};
+use crate::preprocess::{Calibration, Denoise};
 use std::collections::HashMap;
+use std::fmt;
+
+/// Which offsets within `range` count as neighbours
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Von Neumann neighbourhood: offsets with `|dx| + |dy| <= range`, so diagonal touches
+    /// at range 1 don't connect
+    Four,
+    /// Moore neighbourhood: the whole square window of `range`
+    #[default]
+    Eight,
+}
+
+/// Parameters for [`extract`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtractConfig {
+    /// How far (in cells) to look for already-labelled neighbours
+    pub range: i16,
+    pub connectivity: Connectivity,
+    /// Cells at or below this value are background
+    pub threshold: f32,
+    /// Tracks with fewer pixels are dropped; 1 keeps everything
+    pub min_size: usize,
+    /// When set, clusters holding energy peaks that rise at least this far above the valley
+    /// between them are split apart (see [`split_peaks`])
+    pub split_depth: Option<f32>,
+    /// GAMMA hits within this many pixels of each other are merged into one particle,
+    /// undoing charge sharing; 0 disables merging. Only the entry points that classify
+    /// honour it: [`extract_classified`], [`count_types`], batch runs and the viewer.
+    /// [`extract`] and [`extract_particles`] always return the hits unmerged.
+    pub gamma_merge_radius: usize,
+    /// Filter to run over the frame before extraction, see [`crate::preprocess::prepare`].
+    /// [`extract`] itself never filters; callers prepare the frame they keep.
+    pub denoise: Option<Denoise>,
+    /// Conversion to physical units applied by [`crate::preprocess::prepare`] after
+    /// denoising, so `threshold` and the classifier thresholds are in calibrated units
+    pub calibration: Calibration,
+}
+
+impl Default for ExtractConfig {
+    fn default() -> Self {
+        ExtractConfig {
+            range: 1,
+            connectivity: Connectivity::Eight,
+            threshold: 0.0,
+            min_size: 1,
+            split_depth: None,
+            gamma_merge_radius: 0,
+            denoise: None,
+            calibration: Calibration::default(),
+        }
+    }
+}
+
+impl Connectivity {
+    fn reaches(&self, dx: i16, dy: i16, range: i16) -> bool {
+        match self {
+            Connectivity::Four => dx.abs() + dy.abs() <= range,
+            Connectivity::Eight => true,
+        }
+    }
+}
 
 /// Extracts connected particles from a grid.
-pub fn extract(
+///
+/// `grid` is row-major: `grid[y][x]` is the cell in row `y`, column `x`, and its shape is
+/// taken from the grid itself, so frames of any resolution work. The returned tracks hold
+/// `(x, y)` coordinates.
+///
+/// Only cells above `config.threshold` take part (see [`is_signal`]). With the default
+/// threshold of 0, zero and negative values (e.g. residue left after baseline subtraction)
+/// are background; raising it also drops sub-noise hits, while a negative threshold would
+/// admit negative pixels.
+///
+/// Tracks are keyed `1, 2, ...` in the order their first pixel appears in a row-major scan,
+/// independent of how the clusters were merged.
+///
+/// Fails on a grid without values or with rows of differing length (see [`ExtractError`]).
+pub fn extract(grid: &[Vec<f32>], config: &ExtractConfig) -> Result<Tracks, ExtractError> {
+    Ok(extract_with_stats(grid, config)?.0)
+}
+
+/// Pixels of each track by key, as [`extract`] returns them
+pub type Tracks = HashMap<usize, Vec<(usize, usize)>>;
+
+/// Why a grid can't be extracted from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractError {
+    /// The grid has no rows, or its first row holds no values
+    EmptyGrid,
+    /// 1-based `row` has `len` values where the first row has `width`
+    RaggedRows {
+        row: usize,
+        len: usize,
+        width: usize,
+    },
+    /// A sparse hit at `(x, y)` lies outside the `width`×`height` frame it was given for
+    HitOutside {
+        hit: (usize, usize),
+        width: usize,
+        height: usize,
+    },
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::EmptyGrid => write!(f, "the frame holds no values"),
+            ExtractError::RaggedRows { row, len, width } => {
+                write!(f, "row {row} has {len} values, expected {width}")
+            }
+            ExtractError::HitOutside {
+                hit: (x, y),
+                width,
+                height,
+            } => write!(f, "hit ({x}, {y}) lies outside the {width}×{height} frame"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<ShapeError> for ExtractError {
+    fn from(e: ShapeError) -> Self {
+        match e {
+            ShapeError::ZeroWidth => ExtractError::EmptyGrid,
+            ShapeError::Ragged { row, len, width } => ExtractError::RaggedRows { row, len, width },
+        }
+    }
+}
+
+/// How the labelling behind an [`extract`] call went, for tuning `range` and friends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtractStats {
+    /// Times two separately labelled clusters turned out to touch and were joined
+    pub merges: usize,
+    /// Connected clusters, before peak splitting and the size filter
+    pub clusters: usize,
+    /// Pixels in the largest of those clusters
+    pub max_cluster_size: usize,
+}
+
+impl fmt::Display for ExtractStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "clusters: {} ({} merges), largest: {} pixels",
+            self.clusters, self.merges, self.max_cluster_size
+        )
+    }
+}
+
+/// [`extract`], also reporting [`ExtractStats`] about the labelling
+pub fn extract_with_stats(
     grid: &[Vec<f32>],
-    id_map: &mut [Vec<usize>],
+    config: &ExtractConfig,
+) -> Result<(Tracks, ExtractStats), ExtractError> {
+    let mut id_map = new_id_map(grid)?;
+    let (mut parent, _) = label(grid, &mut id_map, config);
+    let tracks = build_tracks(&id_map, &mut parent);
+
+    // every provisional id beyond one per final cluster was merged away
+    let stats = ExtractStats {
+        merges: parent.len() - 1 - tracks.len(),
+        clusters: tracks.len(),
+        max_cluster_size: tracks.values().map(Vec::len).max().unwrap_or(0),
+    };
+    Ok((finish_tracks(tracks, grid, config), stats))
+}
+
+/// [`extract`] for a sparse frame given as `(x, y, energy)` hits on a `width`×`height`
+/// sensor, without building the dense grid. Gives the same tracks, keys and pixel order as
+/// [`extract`] on the dense frame with the default config at `range`: eight-connected, with
+/// only positive energies as signal. A cell listed twice counts once; a hit outside the
+/// frame is an [`ExtractError::HitOutside`].
+pub fn extract_sparse(
+    hits: &[(usize, usize, f32)],
+    width: usize,
+    height: usize,
     range: i16,
-) -> HashMap<usize, Vec<(usize, usize)>> {
-    let mut next_id: usize = 1;
-    let mut parent: HashMap<usize, usize> = HashMap::new();
-    let size_x = grid.len();
-    let size_y = grid[0].len();
-
-    for y in 0..size_x {
-        for x in 0..size_y {
-            if grid[x][y] == 0.0 {
+) -> Result<Tracks, ExtractError> {
+    if let Some(&(x, y, _)) = hits.iter().find(|&&(x, y, _)| x >= width || y >= height) {
+        return Err(ExtractError::HitOutside {
+            hit: (x, y),
+            width,
+            height,
+        });
+    }
+
+    let mut cells: Vec<(usize, usize)> = hits
+        .iter()
+        .filter(|&&(_, _, energy)| is_signal(energy, 0.0))
+        .map(|&(x, y, _)| (x, y))
+        .collect();
+    // the row-major order the dense scan visits them in
+    cells.sort_unstable_by_key(|&(x, y)| (y, x));
+    cells.dedup();
+
+    let mut ids: HashMap<(usize, usize), usize> = HashMap::with_capacity(cells.len());
+    let mut parent: Vec<usize> = vec![0];
+    let mut rank: Vec<u8> = vec![0];
+    let reach = range.max(0) as usize;
+    for &(x, y) in &cells {
+        // the same already visited window as check_surroundings
+        let mut neighbors: Vec<usize> = Vec::new();
+        for ny in y.saturating_sub(reach)..=y {
+            let end = if ny == y {
+                x
+            } else {
+                (x + reach + 1).min(width)
+            };
+            for nx in x.saturating_sub(reach)..end {
+                if let Some(&id) = ids.get(&(nx, ny)) {
+                    neighbors.push(id);
+                }
+            }
+        }
+
+        let id = match neighbors.first() {
+            None => {
+                let id = parent.len();
+                parent.push(id);
+                rank.push(0);
+                id
+            }
+            Some(&first) => {
+                let root = find(first, &mut parent);
+                for &other in &neighbors[1..] {
+                    union(root, other, &mut parent, &mut rank);
+                }
+                root
+            }
+        };
+        ids.insert((x, y), id);
+    }
+
+    // keyed like build_tracks, by first pixel in scan order
+    let mut tracks: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+    let mut keys = vec![0usize; parent.len()];
+    for &(x, y) in &cells {
+        let root = find(ids[&(x, y)], &mut parent);
+        if keys[root] == 0 {
+            keys[root] = tracks.len() + 1;
+        }
+        tracks.entry(keys[root]).or_default().push((x, y));
+    }
+    Ok(tracks)
+}
+
+/// Whether no pixel appears in more than one track (or twice in one)
+fn is_disjoint(tracks: &HashMap<usize, Vec<(usize, usize)>>) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    tracks.values().flatten().all(|&p| seen.insert(p))
+}
+
+/// A zeroed id map with the same shape as `grid`, which is validated here for every
+/// extraction entry point
+fn new_id_map(grid: &[Vec<f32>]) -> Result<Vec<Vec<usize>>, ExtractError> {
+    match check_shape(grid)? {
+        (0, _) => Err(ExtractError::EmptyGrid),
+        (width, height) => Ok(vec![vec![0usize; width]; height]),
+    }
+}
+
+/// Label image of `grid`: every cell holds the key of the [`extract`] track it belongs to,
+/// with all merges resolved, and 0 for background or dropped cells. Row-major like `grid`.
+pub fn label_image(
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+) -> Result<Vec<Vec<usize>>, ExtractError> {
+    let width = grid.first().map_or(0, Vec::len);
+    let tracks = extract(grid, config)?;
+
+    let mut labels = vec![vec![0usize; width]; grid.len()];
+    for (key, track) in tracks {
+        for (x, y) in track {
+            labels[y][x] = key;
+        }
+    }
+    Ok(labels)
+}
+
+/// Runs [`extract`] on `grid` and wraps every track in a [`Particle`].
+///
+/// Particles come in key order, i.e. sorted by their first pixel in a row-major scan, so the
+/// same frame always yields the same sequence.
+pub fn extract_particles(
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+) -> Result<Vec<Particle>, ExtractError> {
+    Ok(into_particles(extract(grid, config)?))
+}
+
+/// Wraps every track of an [`extract`] result in a [`Particle`], in key order as
+/// [`extract_particles`] gives them
+pub fn into_particles(tracks: Tracks) -> Vec<Particle> {
+    let mut tracks: Vec<(usize, Vec<(usize, usize)>)> = tracks.into_iter().collect();
+    tracks.sort_unstable_by_key(|&(key, _)| key);
+
+    tracks
+        .into_iter()
+        .map(|(_, track)| Particle::new(track))
+        .collect()
+}
+
+/// Runs [`extract_particles`], then merges nearby GAMMA hits with [`merge_gammas`] as
+/// `config.gamma_merge_radius` asks. Keeps the first-pixel order of [`extract_particles`].
+pub fn extract_classified(
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+    classifier: &dyn Classifier,
+) -> Result<Vec<Particle>, ExtractError> {
+    let particles = extract_particles(grid, config)?;
+    Ok(merge_gammas(
+        particles,
+        grid,
+        classifier,
+        config.gamma_merge_radius,
+    ))
+}
+
+/// Number of particles of each type in `grid`, the same counts [`extract_classified`] gives.
+///
+/// Unless GAMMA merging is on, each track is wrapped, classified and dropped in turn, so
+/// no list of particles (and their caches) is ever held for the whole frame.
+pub fn count_types(
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+    classifier: &dyn Classifier,
+) -> Result<HashMap<PartType, usize>, ExtractError> {
+    let mut counts = HashMap::new();
+    if config.gamma_merge_radius > 0 {
+        for particle in extract_classified(grid, config, classifier)? {
+            *counts
+                .entry(classifier.classify(&particle, grid))
+                .or_default() += 1;
+        }
+        return Ok(counts);
+    }
+
+    for (_, track) in extract(grid, config)? {
+        let particle = Particle::new(track);
+        *counts
+            .entry(classifier.classify(&particle, grid))
+            .or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// Merges particles classified GAMMA whose pixels lie within `radius` (Chebyshev distance)
+/// of each other, transitively, so one photon split over neighbouring pixels counts once.
+/// A merged cluster keeps the GAMMA type (see [`Particle::with_type`]) rather than being
+/// classified by its shape. Other particles are left alone; a radius of 0 changes nothing.
+pub fn merge_gammas(
+    particles: Vec<Particle>,
+    grid: &[Vec<f32>],
+    classifier: &dyn Classifier,
+    radius: usize,
+) -> Vec<Particle> {
+    if radius == 0 {
+        return particles;
+    }
+
+    let (gammas, mut merged): (Vec<Particle>, Vec<Particle>) = particles
+        .into_iter()
+        .partition(|p| classifier.classify(p, grid) == PartType::GAMMA);
+
+    let mut parent: Vec<usize> = (0..gammas.len()).collect();
+    let mut rank: Vec<u8> = vec![0; gammas.len()];
+    let tracks: Vec<Vec<(usize, usize)>> = gammas.iter().map(Particle::get_track).collect();
+
+    // pixels within `radius` of each other lie in the same or neighbouring square buckets
+    // of side `radius`, so only those are compared
+    let mut buckets: HashMap<_, Vec<_>> = HashMap::new();
+    for (i, track) in tracks.iter().enumerate() {
+        for &(x, y) in track {
+            buckets
+                .entry((x / radius, y / radius))
+                .or_default()
+                .push((i, (x, y)));
+        }
+    }
+    for (i, track) in tracks.iter().enumerate() {
+        for &(ax, ay) in track {
+            let (bx, by) = (ax / radius, ay / radius);
+            for ny in by.saturating_sub(1)..=by + 1 {
+                for nx in bx.saturating_sub(1)..=bx + 1 {
+                    for &(j, (x, y)) in buckets.get(&(nx, ny)).into_iter().flatten() {
+                        if j > i && ax.abs_diff(x).max(ay.abs_diff(y)) <= radius {
+                            union(i, j, &mut parent, &mut rank);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<Vec<(usize, usize)>>> = HashMap::new();
+    for (i, track) in tracks.into_iter().enumerate() {
+        groups.entry(find(i, &mut parent)).or_default().push(track);
+    }
+    merged.extend(groups.into_values().map(|hits| {
+        if hits.len() == 1 {
+            return Particle::new(hits.into_iter().flatten().collect());
+        }
+        let mut track: Vec<(usize, usize)> = hits.into_iter().flatten().collect();
+        track.sort_by_key(|&(x, y)| (y, x));
+        Particle::new(track).with_type(PartType::GAMMA)
+    }));
+    merged.sort_by_key(|p| p.track_ref().first().map(|&(x, y)| (y, x)));
+
+    merged
+}
+
+/// Same result as [`extract`], but labels horizontal strips of the grid on the rayon
+/// thread pool and then stitches clusters that straddle strip seams.
+#[cfg(feature = "rayon")]
+pub fn extract_parallel(grid: &[Vec<f32>], config: &ExtractConfig) -> Result<Tracks, ExtractError> {
+    let strip_height = grid.len().div_ceil(rayon::current_num_threads()).max(1);
+    extract_strips(grid, config, strip_height)
+}
+
+#[cfg(feature = "rayon")]
+fn extract_strips(
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+    strip_height: usize,
+) -> Result<Tracks, ExtractError> {
+    use rayon::prelude::*;
+
+    let mut id_map = new_id_map(grid)?;
+    let strips: Vec<(Vec<usize>, Vec<u8>)> = grid
+        .par_chunks(strip_height)
+        .zip(id_map.par_chunks_mut(strip_height))
+        .map(|(grid_strip, id_strip)| label(grid_strip, id_strip, config))
+        .collect();
+
+    // shift each strip's local ids past those of the strips above it
+    let mut parent: Vec<usize> = vec![0];
+    let mut rank: Vec<u8> = vec![0];
+    for (i, (strip_parent, strip_rank)) in strips.into_iter().enumerate() {
+        let offset = parent.len() - 1;
+        parent.extend(strip_parent[1..].iter().map(|p| p + offset));
+        rank.extend_from_slice(&strip_rank[1..]);
+
+        for row in id_map.iter_mut().skip(i * strip_height).take(strip_height) {
+            for id in row.iter_mut().filter(|id| **id != 0) {
+                *id += offset;
+            }
+        }
+    }
+
+    // only the first `range` rows of a strip can reach cells of an earlier strip
+    let reach = config.range.max(0) as usize;
+    for seam in (strip_height..grid.len()).step_by(strip_height) {
+        let end = (seam + reach).min(seam + strip_height).min(grid.len());
+        for y in seam..end {
+            for x in 0..grid[y].len() {
+                if id_map[y][x] == 0 {
+                    continue;
+                }
+                for other in check_surroundings(&(x, y), grid, &id_map, config) {
+                    union(id_map[y][x], other, &mut parent, &mut rank);
+                }
+            }
+        }
+    }
+
+    Ok(finish_tracks(
+        build_tracks(&id_map, &mut parent),
+        grid,
+        config,
+    ))
+}
+
+/// Gives every signal cell a provisional id in `id_map` and returns the union-find forest
+/// (`parent`, `rank`) over those ids. Ids start at 1; index 0 is a placeholder.
+fn label(
+    grid: &[Vec<f32>],
+    id_map: &mut [Vec<usize>],
+    config: &ExtractConfig,
+) -> (Vec<usize>, Vec<u8>) {
+    let mut parent: Vec<usize> = vec![0];
+    let mut rank: Vec<u8> = vec![0];
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+
+    for y in 0..height {
+        for x in 0..width {
+            if !is_signal(grid[y][x], config.threshold) {
                 continue;
             }
 
-            let neighbors = check_surroundings(&(x, y), grid, id_map, range);
+            let neighbors = check_surroundings(&(x, y), grid, id_map, config);
 
             if neighbors.is_empty() {
-                id_map[x][y] = next_id;
-                parent.insert(next_id, next_id);
-                next_id += 1;
+                let id = parent.len();
+                id_map[y][x] = id;
+                parent.push(id);
+                rank.push(0);
             } else {
                 let root = find(neighbors[0], &mut parent);
-                id_map[x][y] = root;
+                id_map[y][x] = root;
 
                 for &other in &neighbors[1..] {
-                    union(root, other, &mut parent);
+                    union(root, other, &mut parent, &mut rank);
+                }
+            }
+        }
+    }
+
+    (parent, rank)
+}
+
+/// Applies the optional peak split, then the size filter. Debug builds check that no pixel
+/// ended up in two tracks.
+fn finish_tracks(
+    tracks: HashMap<usize, Vec<(usize, usize)>>,
+    grid: &[Vec<f32>],
+    config: &ExtractConfig,
+) -> HashMap<usize, Vec<(usize, usize)>> {
+    let tracks = match config.split_depth {
+        Some(depth) => split_tracks(tracks, grid, depth),
+        None => tracks,
+    };
+    debug_assert!(is_disjoint(&tracks), "a pixel was assigned to two tracks");
+    filter_by_size(tracks, config.min_size)
+}
+
+/// Runs [`split_peaks`] on every track and re-keys the pieces canonically
+fn split_tracks(
+    tracks: HashMap<usize, Vec<(usize, usize)>>,
+    grid: &[Vec<f32>],
+    min_depth: f32,
+) -> HashMap<usize, Vec<(usize, usize)>> {
+    let mut pieces: Vec<Vec<(usize, usize)>> = tracks
+        .into_values()
+        .flat_map(|track| split_peaks(grid, &track, min_depth))
+        .collect();
+    pieces.sort_by_key(|piece| (piece[0].1, piece[0].0));
+
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(i, piece)| (i + 1, piece))
+        .collect()
+}
+
+/// Splits a track along the energy valleys between its peaks (a watershed).
+///
+/// Pixels are flooded from the highest energy down. Where two basins meet, the one with the
+/// lower peak is merged into the other unless that peak rises at least `min_depth` above the
+/// meeting level, so noise bumps stay attached while clearly separated peaks become their own
+/// track. Pieces keep the pixel order of `track`.
+///
+/// Every pixel belongs to exactly one basin, so the pieces partition `track`: each pixel
+/// lands in one piece, and none is duplicated or lost.
+pub fn split_peaks(
+    grid: &[Vec<f32>],
+    track: &[(usize, usize)],
+    min_depth: f32,
+) -> Vec<Vec<(usize, usize)>> {
+    let index: HashMap<(usize, usize), usize> =
+        track.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+    let energy = |i: usize| grid[track[i].1][track[i].0];
+
+    let mut order: Vec<usize> = (0..track.len()).collect();
+    order.sort_by(|&a, &b| energy(b).total_cmp(&energy(a)));
+
+    // basin of every flooded pixel; basins are union-find nodes with their peak energy
+    let mut basin: Vec<Option<usize>> = vec![None; track.len()];
+    let mut parent: Vec<usize> = Vec::new();
+    let mut peak: Vec<f32> = Vec::new();
+
+    for &i in &order {
+        let (x, y) = track[i];
+        let level = energy(i);
+        let mut roots: Vec<usize> = Vec::new();
+        for ny in y.saturating_sub(1)..=y + 1 {
+            for nx in x.saturating_sub(1)..=x + 1 {
+                if let Some(&j) = index.get(&(nx, ny))
+                    && let Some(b) = basin[j]
+                {
+                    let root = find(b, &mut parent);
+                    if !roots.contains(&root) {
+                        roots.push(root);
+                    }
                 }
             }
         }
+
+        // highest peak first, so the pixel joins the dominant basin
+        roots.sort_by(|&a, &b| peak[b].total_cmp(&peak[a]));
+        let Some(&top) = roots.first() else {
+            basin[i] = Some(parent.len());
+            parent.push(parent.len());
+            peak.push(level);
+            continue;
+        };
+        for &other in &roots[1..] {
+            if peak[other] - level < min_depth {
+                parent[other] = top;
+            }
+        }
+        basin[i] = Some(top);
+    }
+
+    let mut pieces: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut keys: HashMap<usize, usize> = HashMap::new();
+    for (i, &p) in track.iter().enumerate() {
+        let root = find(basin[i].unwrap(), &mut parent);
+        let key = *keys.entry(root).or_insert_with(|| {
+            pieces.push(Vec::new());
+            pieces.len() - 1
+        });
+        pieces[key].push(p);
     }
 
-    build_tracks(id_map, &mut parent)
+    pieces
+}
+
+/// Removes tracks with fewer than `min_size` pixels
+pub fn filter_by_size(
+    mut tracks: HashMap<usize, Vec<(usize, usize)>>,
+    min_size: usize,
+) -> HashMap<usize, Vec<(usize, usize)>> {
+    tracks.retain(|_, track| track.len() >= min_size);
+    tracks
+}
+
+/// Whether a cell value counts as a hit rather than background
+pub fn is_signal(value: f32, threshold: f32) -> bool {
+    value > threshold
+}
+
+/// Collects the 8-connected cluster of cells above `threshold` that contains the `(x, y)` seed.
+/// Returns an empty track if the seed itself is not above the threshold.
+pub fn flood_fill(grid: &[Vec<f32>], seed: (usize, usize), threshold: f32) -> Vec<(usize, usize)> {
+    let height = grid.len();
+    let width = if height == 0 { 0 } else { grid[0].len() };
+    let (sx, sy) = seed;
+
+    if sx >= width || sy >= height || grid[sy][sx] <= threshold {
+        return Vec::new();
+    }
+
+    let mut visited = vec![vec![false; width]; height];
+    let mut stack = vec![seed];
+    let mut track = Vec::new();
+    visited[sy][sx] = true;
+
+    while let Some((x, y)) = stack.pop() {
+        track.push((x, y));
+
+        for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                if !visited[ny][nx] && grid[ny][nx] > threshold {
+                    visited[ny][nx] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+
+    track
 }
 
 /// Builds a map of particle IDs to their coordinates.
+/// Each root gets the next free key the first time the scan meets it.
 fn build_tracks(
     id_map: &[Vec<usize>],
-    parent: &mut HashMap<usize, usize>,
+    parent: &mut [usize],
 ) -> HashMap<usize, Vec<(usize, usize)>> {
     let mut tracks: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
-    let size_x = id_map.len();
-    let size_y = id_map[0].len();
+    let mut keys = vec![0usize; parent.len()];
 
-    for (y, id_y) in id_map.iter().enumerate().take(size_x) {
-        for (x, id) in id_y.iter().enumerate().take(size_y) {
+    for (y, row) in id_map.iter().enumerate() {
+        for (x, id) in row.iter().enumerate() {
             if *id == 0 {
                 continue;
             }
 
             let root = find(*id, parent);
-            tracks.entry(root).or_default().push((x, y));
+            if keys[root] == 0 {
+                keys[root] = tracks.len() + 1;
+            }
+            tracks.entry(keys[root]).or_default().push((x, y));
         }
     }
 
@@ -61,64 +697,106 @@ fn build_tracks(
 }
 
 /// Finds the root of a particle ID (with path compression)
-fn find(x: usize, parent: &mut HashMap<usize, usize>) -> usize {
-    let p = parent[&x];
-    if p != x {
-        let root = find(p, parent);
-        parent.insert(x, root);
-        root
-    } else {
-        x
+fn find(x: usize, parent: &mut [usize]) -> usize {
+    let mut root = x;
+    while parent[root] != root {
+        root = parent[root];
+    }
+
+    let mut current = x;
+    while parent[current] != root {
+        let next = parent[current];
+        parent[current] = root;
+        current = next;
     }
+
+    root
 }
 
-/// Unions two particle IDs
-fn union(a: usize, b: usize, parent: &mut HashMap<usize, usize>) {
+/// Unions two particle IDs (by rank)
+fn union(a: usize, b: usize, parent: &mut [usize], rank: &mut [u8]) {
     let ra = find(a, parent);
     let rb = find(b, parent);
-    if ra != rb {
-        parent.insert(rb, ra);
+    if ra == rb {
+        return;
+    }
+
+    match rank[ra].cmp(&rank[rb]) {
+        std::cmp::Ordering::Less => parent[ra] = rb,
+        std::cmp::Ordering::Greater => parent[rb] = ra,
+        std::cmp::Ordering::Equal => {
+            parent[rb] = ra;
+            rank[ra] += 1;
+        }
     }
 }
 
-/// Checks all previously uncovered cells in range
+/// Checks all previously uncovered cells in range of the `(x, y)` location.
+///
+/// [`label`] scans `for y { for x }`, so the cells already visited are every row above
+/// (any `dx`, including up-and-to-the-right) plus the cells to the left on the same row.
+/// Together with the later cells looking back at this one, that covers every pair within
+/// range exactly once. Returns the distinct ids found, in ascending order.
 pub fn check_surroundings(
     location: &(usize, usize),
     grid: &[Vec<f32>],
     id_map: &[Vec<usize>],
-    range: i16,
+    config: &ExtractConfig,
 ) -> Vec<usize> {
+    let range = config.range;
     let mut found_ids: Vec<usize> = Vec::new();
-    let size_x = grid.len() as i16;
-    let size_y = grid[0].len() as i16;
+    let size_x = grid[0].len() as i16;
+    let size_y = grid.len() as i16;
 
     let (lx, ly) = (location.0 as i16, location.1 as i16);
 
-    // check all cells above and diagonals
+    // every cell in the rows above, on both sides
     for dx in -range..=range {
         for dy in -range..0 {
-            if let Some(id) = check_cell((lx, ly), dx, dy, size_x, size_y, grid, id_map)
-                && !found_ids.contains(&id)
-            {
+            if !config.connectivity.reaches(dx, dy, range) {
+                continue;
+            }
+            if let Some(id) = check_cell(
+                (lx, ly),
+                dx,
+                dy,
+                size_x,
+                size_y,
+                grid,
+                id_map,
+                config.threshold,
+            ) {
                 found_ids.push(id);
             }
         }
     }
 
-    // check cells left
+    // cells left on the same row
     for dx in -range..0 {
         let dy = 0;
-        if let Some(id) = check_cell((lx, ly), dx, dy, size_x, size_y, grid, id_map)
-            && !found_ids.contains(&id)
-        {
+        if let Some(id) = check_cell(
+            (lx, ly),
+            dx,
+            dy,
+            size_x,
+            size_y,
+            grid,
+            id_map,
+            config.threshold,
+        ) {
             found_ids.push(id);
         }
     }
 
+    // a sort beats a linear `contains` per cell once large ranges find hundreds of ids
+    found_ids.sort_unstable();
+    found_ids.dedup();
     found_ids
 }
 
-/// Checks a single cell at offset
+/// Checks a single cell at offset from the `(x, y)` location.
+/// `size_x` is the grid width (row length) and `size_y` its height (row count).
+#[allow(clippy::too_many_arguments)]
 pub fn check_cell(
     loc: (i16, i16),
     dx: i16,
@@ -127,6 +805,7 @@ pub fn check_cell(
     size_y: i16,
     grid: &[Vec<f32>],
     id_map: &[Vec<usize>],
+    threshold: f32,
 ) -> Option<usize> {
     let x = loc.0 + dx;
     let y = loc.1 + dy;
@@ -137,8 +816,8 @@ pub fn check_cell(
 
     let (x, y) = (x as usize, y as usize);
 
-    if grid[x][y] > 0.0 {
-        let id = id_map[x][y];
+    if is_signal(grid[y][x], threshold) {
+        let id = id_map[y][x];
         if id != 0 {
             return Some(id);
         }
@@ -183,13 +862,600 @@ mod tests {
         let grid = get_grid();
 
         assert_eq!(
-            check_cell((3, 3), -1, -1, 256, 256, &grid, &id_map),
+            check_cell((3, 3), -1, -1, 256, 256, &grid, &id_map, 0.0),
             Some(1)
         );
-        assert_eq!(check_cell((3, 3), -2, -2, 256, 256, &grid, &id_map), None);
         assert_eq!(
-            check_cell((5, 5), -2, -2, 256, 256, &grid, &id_map),
+            check_cell((3, 3), -2, -2, 256, 256, &grid, &id_map, 0.0),
+            None
+        );
+        assert_eq!(
+            check_cell((5, 5), -2, -2, 256, 256, &grid, &id_map, 0.0),
             Some(5)
         );
     }
+
+    #[test]
+    fn test_non_square_grid() {
+        // 10 rows, 20 columns; the particle sits in columns no row index could reach
+        let mut grid = vec![vec![0.0f32; 20]; 10];
+        grid[2][15] = 3.0;
+        grid[2][16] = 4.0;
+        grid[3][16] = 5.0;
+
+        let tracks = extract(&grid, &ExtractConfig::default()).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        let mut track = tracks.values().next().unwrap().clone();
+        track.sort();
+        assert_eq!(track, vec![(15, 2), (16, 2), (16, 3)]);
+
+        let particle = crate::decoder::Particle::new(track);
+        assert_eq!(particle.total_energy(&grid), 12.0);
+        assert_eq!(particle.max_energy(&grid), 5.0);
+    }
+
+    #[test]
+    fn test_connectivity() {
+        // an X: centre plus the four diagonal neighbours
+        let mut grid = vec![vec![0.0f32; 5]; 5];
+        for (x, y) in [(2, 2), (1, 1), (3, 1), (1, 3), (3, 3)] {
+            grid[y][x] = 1.0;
+        }
+
+        assert_eq!(extract(&grid, &ExtractConfig::default()).unwrap().len(), 1);
+
+        let four = ExtractConfig {
+            connectivity: Connectivity::Four,
+            ..ExtractConfig::default()
+        };
+        assert_eq!(extract(&grid, &four).unwrap().len(), 5);
+
+        // at range 2 the diagonals are within Manhattan distance of the centre
+        let four_wide = ExtractConfig { range: 2, ..four };
+        assert_eq!(extract(&grid, &four_wide).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_threshold_drops_noise() {
+        let mut grid = get_grid();
+        // isolated sub-noise hits between the two particles
+        for y in (20..200).step_by(3) {
+            for x in (20..200).step_by(3) {
+                grid[y][x] = 0.3;
+            }
+        }
+
+        assert_eq!(
+            extract(&grid, &ExtractConfig::default()).unwrap().len(),
+            3602
+        );
+
+        let config = ExtractConfig {
+            threshold: 0.5,
+            ..ExtractConfig::default()
+        };
+        assert_eq!(extract(&grid, &config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_min_size() {
+        let mut grid = get_grid();
+        grid[100][100] = 1.0;
+        grid[120][120] = 1.0;
+        grid[120][121] = 1.0;
+
+        assert_eq!(extract(&grid, &ExtractConfig::default()).unwrap().len(), 4);
+
+        let config = ExtractConfig {
+            min_size: 3,
+            ..ExtractConfig::default()
+        };
+        assert_eq!(extract(&grid, &config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_split_peaks() {
+        // two alpha-like blobs whose edges touch along column 7
+        let mut grid = vec![vec![0.0f32; 16]; 10];
+        for (cx, cy) in [(4usize, 4usize), (10, 4)] {
+            for (y, row) in grid.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    let d = x.abs_diff(cx).max(y.abs_diff(cy));
+                    if d <= 3 {
+                        *cell = cell.max(100.0 - 25.0 * d as f32);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(extract(&grid, &ExtractConfig::default()).unwrap().len(), 1);
+
+        let config = ExtractConfig {
+            split_depth: Some(20.0),
+            ..ExtractConfig::default()
+        };
+        let tracks = extract(&grid, &config).unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks.values().map(Vec::len).sum::<usize>(), 13 * 7);
+        assert!(tracks[&1].contains(&(4, 4)));
+        assert!(tracks[&2].contains(&(10, 4)));
+
+        assert!(is_disjoint(&tracks));
+        let mut pixels: Vec<(usize, usize)> = tracks.values().flatten().copied().collect();
+        pixels.sort_unstable();
+        let mut unsplit = extract(&grid, &ExtractConfig::default())
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+        unsplit.sort_unstable();
+        assert_eq!(pixels, unsplit);
+
+        let mut leaked = tracks.clone();
+        leaked.get_mut(&2).unwrap().push((4, 4));
+        assert!(!is_disjoint(&leaked));
+
+        // a valley shallower than the required depth keeps the blobs together
+        let config = ExtractConfig {
+            split_depth: Some(90.0),
+            ..ExtractConfig::default()
+        };
+        assert_eq!(extract(&grid, &config).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_gammas() {
+        // two charge-shared photon hits split by a one-pixel gap each, and one lone hit
+        let mut grid = vec![vec![0.0f32; 64]; 64];
+        for (x, y) in [(10, 10), (12, 10), (30, 30), (30, 32), (50, 50)] {
+            grid[y][x] = 30.0;
+        }
+        let classifier = crate::classifier::HeuristicClassifier::default();
+        let count = |config: &ExtractConfig| {
+            let particles = extract_classified(&grid, config, &classifier).unwrap();
+            assert!(
+                particles
+                    .iter()
+                    .all(|p| classifier.classify(p, &grid) == PartType::GAMMA)
+            );
+            particles.len()
+        };
+
+        assert_eq!(count(&ExtractConfig::default()), 5);
+        let merging = ExtractConfig {
+            gamma_merge_radius: 2,
+            ..ExtractConfig::default()
+        };
+        assert_eq!(count(&merging), 3);
+
+        // four hits in a square merge into a cluster too big for a GAMMA by shape
+        let mut grid = grid.clone();
+        for (x, y) in [(40, 10), (42, 10), (40, 12), (42, 12)] {
+            grid[y][x] = 30.0;
+        }
+        let particles = extract_classified(&grid, &merging, &classifier).unwrap();
+        assert_eq!(particles.len(), 4);
+        assert!(
+            particles
+                .iter()
+                .all(|p| classifier.classify(p, &grid) == PartType::GAMMA)
+        );
+        let square = particles.iter().find(|p| p.size() == 4).unwrap();
+        assert_eq!(square.fixed_type(), Some(PartType::GAMMA));
+        assert_ne!(
+            Particle::new(square.get_track()).particle_type(&grid),
+            PartType::GAMMA
+        );
+    }
+
+    #[test]
+    fn test_merge_gammas_buckets() {
+        // hits strung out along a row, each within the radius of the next only, plus
+        // pairs straddling bucket edges
+        let mut grid = vec![vec![0.0f32; 96]; 8];
+        for x in (0..40).step_by(3) {
+            grid[1][x] = 30.0;
+        }
+        for (x, y) in [(59, 4), (62, 6), (80, 1), (84, 1)] {
+            grid[y][x] = 30.0;
+        }
+        let classifier = crate::classifier::HeuristicClassifier::default();
+        let particles = extract_particles(&grid, &ExtractConfig::default()).unwrap();
+        let merged = merge_gammas(particles, &grid, &classifier, 3);
+        let sizes: Vec<usize> = merged.iter().map(Particle::size).collect();
+        assert_eq!(sizes, vec![14, 1, 1, 2]);
+    }
+
+    #[test]
+    fn test_up_right_neighbours() {
+        // a staircase rising to the right: each pixel's only earlier neighbour is up-right
+        let mut grid = vec![vec![0.0f32; 12]; 12];
+        for i in 0..8 {
+            grid[8 - i][2 + i] = 1.0;
+        }
+        assert_eq!(extract(&grid, &ExtractConfig::default()).unwrap().len(), 1);
+
+        // a gap of one pixel up-right is bridged at range 2 and, with four-connectivity,
+        // only when the offset stays within the diamond
+        let mut grid = vec![vec![0.0f32; 12]; 12];
+        grid[3][6] = 1.0;
+        grid[5][5] = 1.0;
+        let range_2 = ExtractConfig {
+            range: 2,
+            ..ExtractConfig::default()
+        };
+        assert_eq!(extract(&grid, &range_2).unwrap().len(), 1);
+        let diamond = ExtractConfig {
+            connectivity: Connectivity::Four,
+            ..range_2
+        };
+        assert_eq!(extract(&grid, &diamond).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_surroundings_deduplicated() {
+        // a dense frame labelled in stripes, so large windows see each id many times
+        let grid = vec![vec![1.0f32; 30]; 30];
+        let id_map: Vec<Vec<usize>> = (0..30)
+            .map(|y| (0..30).map(|x| 1 + (x / 3 + y / 7) % 6).collect())
+            .collect();
+
+        for range in [1, 2, 10] {
+            let config = ExtractConfig {
+                range,
+                ..ExtractConfig::default()
+            };
+            for (x, y) in [(0usize, 0usize), (15, 15), (29, 3), (4, 29)] {
+                // every cell of the window visited before this one in scan order
+                let reach = range as usize;
+                let expected: std::collections::BTreeSet<usize> = (0..30)
+                    .flat_map(|ny| (0..30).map(move |nx| (nx, ny)))
+                    .filter(|&(nx, ny)| ny < y || (ny == y && nx < x))
+                    .filter(|&(nx, ny)| nx.abs_diff(x) <= reach && ny.abs_diff(y) <= reach)
+                    .map(|(nx, ny)| id_map[ny][nx])
+                    .collect();
+
+                let found = check_surroundings(&(x, y), &grid, &id_map, &config);
+                assert_eq!(
+                    found,
+                    expected.into_iter().collect::<Vec<_>>(),
+                    "{range} {x} {y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let mut grid = get_grid();
+        // scattered hits, some close enough to join at the larger ranges
+        let mut state = 12345u32;
+        for _ in 0..400 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let (x, y) = ((state >> 8) as usize % 256, (state >> 20) as usize % 200);
+            grid[y][x] = 5.0;
+        }
+        let mut hits: Vec<(usize, usize, f32)> = grid
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, &v)| (x, y, v)))
+            .filter(|&(_, _, v)| v != 0.0)
+            .rev()
+            .collect();
+        // a duplicate and an empty hit change nothing
+        hits.push(hits[0]);
+        hits.push((3, 3, 0.0));
+
+        for range in [1, 2, 4] {
+            let config = ExtractConfig {
+                range,
+                ..ExtractConfig::default()
+            };
+            assert_eq!(
+                extract_sparse(&hits, 256, 256, range).unwrap(),
+                extract(&grid, &config).unwrap(),
+                "{range}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparse_hit_outside() {
+        let err = extract_sparse(&[(1, 1, 1.0), (8, 2, 1.0)], 8, 8, 1).unwrap_err();
+        assert_eq!(
+            err,
+            ExtractError::HitOutside {
+                hit: (8, 2),
+                width: 8,
+                height: 8
+            }
+        );
+        assert_eq!(err.to_string(), "hit (8, 2) lies outside the 8×8 frame");
+    }
+
+    #[test]
+    fn test_count_types() {
+        let grid = get_grid();
+        let classifier = crate::classifier::HeuristicClassifier::default();
+
+        for config in [
+            ExtractConfig::default(),
+            ExtractConfig {
+                gamma_merge_radius: 3,
+                ..ExtractConfig::default()
+            },
+        ] {
+            let mut expected: HashMap<PartType, usize> = HashMap::new();
+            for particle in extract_classified(&grid, &config, &classifier).unwrap() {
+                *expected
+                    .entry(classifier.classify(&particle, &grid))
+                    .or_default() += 1;
+            }
+            assert!(!expected.is_empty());
+            assert_eq!(count_types(&grid, &config, &classifier).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_label_image() {
+        // a U whose arms only join at the bottom row, so its pixels start with separate ids
+        let mut grid = vec![vec![0.0f32; 8]; 6];
+        for row in grid.iter_mut().take(5) {
+            row[1] = 1.0;
+            row[5] = 1.0;
+        }
+        grid[4][1..=5].fill(1.0);
+        grid[0][7] = 1.0;
+
+        let labels = label_image(&grid, &ExtractConfig::default()).unwrap();
+        let mut expected: Vec<Vec<usize>> = grid
+            .iter()
+            .map(|row| row.iter().map(|&v| usize::from(v > 0.0)).collect())
+            .collect();
+        expected[0][7] = 2;
+
+        assert_eq!(labels, expected);
+    }
+
+    #[test]
+    fn test_empty_grid() {
+        let config = ExtractConfig::default();
+        assert_eq!(extract(&[], &config), Err(ExtractError::EmptyGrid));
+        // used to come out as a 0-wide frame with nothing in it
+        let grid = vec![vec![], vec![0.0, 5.0, 5.0], vec![0.0, 5.0, 0.0]];
+        assert_eq!(extract(&grid, &config), Err(ExtractError::EmptyGrid));
+        assert_eq!(
+            extract_particles(&grid, &config).err(),
+            Some(ExtractError::EmptyGrid)
+        );
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let grid = vec![vec![0.0, 5.0, 5.0], vec![0.0, 5.0, 0.0], vec![5.0, 5.0]];
+        let err = extract(&grid, &ExtractConfig::default()).unwrap_err();
+        assert_eq!(
+            err,
+            ExtractError::RaggedRows {
+                row: 3,
+                len: 2,
+                width: 3
+            }
+        );
+        assert_eq!(err.to_string(), "row 3 has 2 values, expected 3");
+        assert_eq!(label_image(&grid, &ExtractConfig::default()), Err(err));
+    }
+
+    #[test]
+    fn test_non_square_frame() {
+        // 140 rows of 100 values, with tracks against the far edges
+        let mut grid = vec![vec![0.0f32; 100]; 140];
+        grid[139][90..100].fill(5.0);
+        for row in &mut grid[0..30] {
+            row[99] = 5.0;
+        }
+        grid[70][50] = 5.0;
+
+        let particles = extract_particles(&grid, &ExtractConfig::default()).unwrap();
+        let sizes: Vec<usize> = particles.iter().map(Particle::size).collect();
+        assert_eq!(sizes, vec![30, 1, 10]);
+        assert_eq!(particles[2].get_track().last(), Some(&(99, 139)));
+
+        let labels = label_image(&grid, &ExtractConfig::default()).unwrap();
+        assert_eq!((labels.len(), labels[0].len()), (140, 100));
+        assert_eq!(labels[139][99], 3);
+    }
+
+    #[test]
+    fn test_extract_stats() {
+        // the U of test_label_image starts as two clusters that merge on the bottom row
+        let mut grid = vec![vec![0.0f32; 8]; 6];
+        for row in grid.iter_mut().take(5) {
+            row[1] = 1.0;
+            row[5] = 1.0;
+        }
+        grid[4][1..=5].fill(1.0);
+        grid[0][7] = 1.0;
+        let config = ExtractConfig {
+            min_size: 2,
+            ..ExtractConfig::default()
+        };
+
+        let (tracks, stats) = extract_with_stats(&grid, &config).unwrap();
+        assert_eq!(tracks, extract(&grid, &config).unwrap());
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(
+            stats,
+            ExtractStats {
+                merges: 1,
+                clusters: 2,
+                max_cluster_size: 13,
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "clusters: 2 (1 merges), largest: 13 pixels"
+        );
+    }
+
+    #[test]
+    fn test_particle_order_is_stable() {
+        let mut grid = get_grid();
+        for (x, y) in [(100, 20), (30, 200), (40, 40), (41, 40), (200, 100)] {
+            grid[y][x] = 1.0;
+        }
+        let config = ExtractConfig {
+            gamma_merge_radius: 3,
+            ..ExtractConfig::default()
+        };
+        let classifier = crate::classifier::HeuristicClassifier::default();
+        let first_pixels = || -> Vec<(usize, usize)> {
+            extract_classified(&grid, &config, &classifier)
+                .unwrap()
+                .iter()
+                .map(|p| p.get_track()[0])
+                .collect()
+        };
+
+        let order = first_pixels();
+        assert_eq!(
+            order,
+            vec![
+                (2, 2),
+                (100, 20),
+                (40, 40),
+                (200, 100),
+                (30, 200),
+                (246, 246)
+            ]
+        );
+        assert_eq!(first_pixels(), order);
+    }
+
+    #[test]
+    fn test_particle_spanning_grid() {
+        // a serpentine covering every other row, joined at alternating ends
+        let size = 512;
+        let mut grid = vec![vec![0.0f32; size]; size];
+        for (y, row) in grid.iter_mut().enumerate() {
+            if y % 2 == 0 {
+                row.fill(1.0);
+            } else if y % 4 == 1 {
+                row[size - 1] = 1.0;
+            } else {
+                row[0] = 1.0;
+            }
+        }
+
+        let tracks = extract(&grid, &ExtractConfig::default()).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(
+            tracks.values().next().unwrap().len(),
+            size * size / 2 + size / 2
+        );
+    }
+
+    #[test]
+    fn test_long_diagonal_track() {
+        // 5000 diagonal steps zigzagging down a strip, so the dense grid stays small
+        let (length, width) = (5000, 64);
+        let mut grid = vec![vec![0.0f32; width]; length];
+        for (y, row) in grid.iter_mut().enumerate() {
+            let phase = y % (2 * (width - 1));
+            row[phase.min(2 * (width - 1) - phase)] = 1.0;
+        }
+
+        let tracks = extract(&grid, &ExtractConfig::default()).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks.values().next().unwrap().len(), length);
+
+        // the straight anti-diagonal of a 5000×5000 frame, given as hits
+        let hits: Vec<_> = (0..length).map(|i| (length - 1 - i, i, 1.0)).collect();
+        let tracks = extract_sparse(&hits, length, length, 1).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks.values().next().unwrap().len(), length);
+
+        let size = 512;
+
+        // a comb: every tooth gets its own label, and the bar along the bottom merges
+        // them one after another
+        let mut grid = vec![vec![0.0f32; size]; 64];
+        for row in &mut grid[..63] {
+            for x in (0..size).step_by(2) {
+                row[x] = 1.0;
+            }
+        }
+        grid[63].fill(1.0);
+        let (tracks, stats) = extract_with_stats(&grid, &ExtractConfig::default()).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(stats.merges, size / 2 - 1);
+        assert_eq!(tracks[&1].len(), 63 * size / 2 + size);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_matches_serial() {
+        let mut grid = get_grid();
+        // tracks crossing many strip seams
+        for i in 0..200 {
+            grid[20 + i][30 + i / 3] = 2.0;
+            grid[220 - i][100 + i / 2] = 3.0;
+        }
+        // a pair only range 2 connects, split across a seam
+        grid[60][200] = 1.0;
+        grid[62][200] = 1.0;
+
+        let configs = [
+            ExtractConfig::default(),
+            ExtractConfig {
+                range: 2,
+                ..ExtractConfig::default()
+            },
+            ExtractConfig {
+                connectivity: Connectivity::Four,
+                ..ExtractConfig::default()
+            },
+        ];
+
+        for config in &configs {
+            let serial = extract(&grid, config).unwrap();
+
+            for strip_height in [1, 2, 3, 7, 61, 256] {
+                assert_eq!(extract_strips(&grid, config, strip_height).unwrap(), serial);
+            }
+
+            assert_eq!(extract_parallel(&grid, config).unwrap(), serial);
+        }
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let grid = get_grid();
+
+        assert_eq!(flood_fill(&grid, (5, 5), 0.0).len(), 49);
+        assert_eq!(flood_fill(&grid, (250, 250), 0.0).len(), 81);
+        assert!(flood_fill(&grid, (100, 100), 0.0).is_empty());
+        assert!(flood_fill(&grid, (5, 5), 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_negative_baseline_is_background() {
+        let mut grid = get_grid();
+        for row in grid.iter_mut() {
+            for val in row.iter_mut() {
+                if *val == 0.0 {
+                    *val = -0.2;
+                }
+            }
+        }
+
+        let tracks = extract(&grid, &ExtractConfig::default()).unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        let mut sizes: Vec<usize> = tracks.values().map(|t| t.len()).collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![49, 81]);
+    }
 }
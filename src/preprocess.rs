@@ -0,0 +1,247 @@
+use crate::particle_extractor::ExtractConfig;
+
+/// Smoothing filters that can run over a frame before extraction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denoise {
+    /// 3×3 median: removes isolated hot pixels (salt-and-pepper noise) but also erodes
+    /// features thinner than two pixels
+    Median,
+    /// 3×3 binomial approximation of a Gaussian blur
+    Gaussian,
+}
+
+impl std::str::FromStr for Denoise {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "median" => Ok(Denoise::Median),
+            "gaussian" => Ok(Denoise::Gaussian),
+            _ => Err(format!("unknown filter `{s}`")),
+        }
+    }
+}
+
+/// Linear detector calibration from raw ADU to physical units (e.g. keV)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    /// Physical units per ADU
+    pub gain: f32,
+    pub offset: f32,
+}
+
+impl Default for Calibration {
+    /// The identity, leaving raw values as they are
+    fn default() -> Self {
+        Calibration {
+            gain: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+/// Returns `grid` converted with `value * gain + offset`. Empty (zero) cells stay zero, so an
+/// offset doesn't light up the background.
+pub fn calibrate(grid: &[Vec<f32>], gain: f32, offset: f32) -> Vec<Vec<f32>> {
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&v| if v == 0.0 { 0.0 } else { v * gain + offset })
+                .collect()
+        })
+        .collect()
+}
+
+/// A reference frame whose shape differs from the frame it should be subtracted from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeMismatch {
+    /// `(width, height)` of the frame
+    pub frame: (usize, usize),
+    /// `(width, height)` of the reference, that of its first row if it is ragged
+    pub reference: (usize, usize),
+}
+
+impl std::fmt::Display for ShapeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ((fw, fh), (rw, rh)) = (self.frame, self.reference);
+        write!(f, "the reference is {rw}×{rh}, the frame {fw}×{fh}")
+    }
+}
+
+impl std::error::Error for ShapeMismatch {}
+
+/// Returns `grid` minus a dark `reference` frame taken without beam, removing each pixel's
+/// baseline. Differences below 0 are clamped to 0 so the noise left over stays background.
+pub fn subtract_background(
+    grid: &[Vec<f32>],
+    reference: &[Vec<f32>],
+) -> Result<Vec<Vec<f32>>, ShapeMismatch> {
+    let same_shape = grid.len() == reference.len()
+        && grid.iter().zip(reference).all(|(a, b)| a.len() == b.len());
+    if !same_shape {
+        let shape = |g: &[Vec<f32>]| (g.first().map_or(0, Vec::len), g.len());
+        return Err(ShapeMismatch {
+            frame: shape(grid),
+            reference: shape(reference),
+        });
+    }
+
+    Ok(grid
+        .iter()
+        .zip(reference)
+        .map(|(row, dark)| {
+            row.iter()
+                .zip(dark)
+                .map(|(&v, &d)| (v - d).max(0.0))
+                .collect()
+        })
+        .collect())
+}
+
+/// Returns a filtered copy of `grid`. Windows are cut off at the border rather than padded.
+pub fn denoise(grid: &[Vec<f32>], kind: Denoise) -> Vec<Vec<f32>> {
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+    let mut out = vec![vec![0.0f32; width]; height];
+    let mut window: Vec<f32> = Vec::with_capacity(9);
+
+    for (y, row) in out.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let ys = y.saturating_sub(1)..=(y + 1).min(height - 1);
+            let xs = x.saturating_sub(1)..=(x + 1).min(width - 1);
+
+            *cell = match kind {
+                Denoise::Median => {
+                    window.clear();
+                    for ny in ys {
+                        window.extend_from_slice(&grid[ny][xs.clone()]);
+                    }
+                    window.sort_unstable_by(f32::total_cmp);
+                    window[window.len() / 2]
+                }
+                Denoise::Gaussian => {
+                    let (mut sum, mut weights) = (0.0, 0.0);
+                    for ny in ys {
+                        for nx in xs.clone() {
+                            let w = (2 - ny.abs_diff(y)) * (2 - nx.abs_diff(x));
+                            sum += grid[ny][nx] * w as f32;
+                            weights += w as f32;
+                        }
+                    }
+                    sum / weights
+                }
+            };
+        }
+    }
+
+    out
+}
+
+/// Applies the preprocessing `config` asks for: the denoise filter on the raw values, then
+/// the calibration. Hands the frame back unchanged when neither is set.
+pub fn prepare(grid: Vec<Vec<f32>>, config: &ExtractConfig) -> Vec<Vec<f32>> {
+    let grid = match config.denoise {
+        Some(kind) => denoise(&grid, kind),
+        None => grid,
+    };
+    let Calibration { gain, offset } = config.calibration;
+    if config.calibration == Calibration::default() {
+        grid
+    } else {
+        calibrate(&grid, gain, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::PartType;
+    use crate::particle_extractor::extract_particles;
+
+    #[test]
+    fn test_median_removes_isolated_hits() {
+        // a three-pixel-thick track plus scattered single hot pixels
+        let mut grid = vec![vec![0.0f32; 32]; 32];
+        for row in &mut grid[10..13] {
+            row[4..28].fill(50.0);
+        }
+        for (x, y) in [(2, 2), (20, 25), (30, 5), (15, 18)] {
+            grid[y][x] = 80.0;
+        }
+        let config = ExtractConfig::default();
+        assert_eq!(extract_particles(&grid, &config).unwrap().len(), 5);
+
+        let filtered = denoise(&grid, Denoise::Median);
+        let particles = extract_particles(&filtered, &config).unwrap();
+        assert_eq!(particles.len(), 1);
+        // only the four corners of the track are lost
+        assert_eq!(particles[0].size(), 3 * 24 - 4);
+        assert!(filtered[11][4..28].iter().all(|&v| v == 50.0));
+    }
+
+    #[test]
+    fn test_gaussian_preserves_energy_away_from_edges() {
+        let mut grid = vec![vec![0.0f32; 9]; 9];
+        grid[4][4] = 16.0;
+
+        let blurred = denoise(&grid, Denoise::Gaussian);
+        assert_eq!(blurred[4][4], 4.0);
+        assert_eq!(blurred[4][5], 2.0);
+        assert_eq!(blurred[5][5], 1.0);
+        assert_eq!(blurred.iter().flatten().sum::<f32>(), 16.0);
+    }
+
+    #[test]
+    fn test_subtract_background() {
+        let mut grid = vec![vec![3.0f32; 16]; 12];
+        grid[5][2..10].fill(60.0);
+        grid[9][9] = 40.0;
+
+        let clean = subtract_background(&grid, &grid).unwrap();
+        assert!(clean.iter().flatten().all(|&v| v == 0.0));
+        let config = ExtractConfig::default();
+        assert!(extract_particles(&clean, &config).unwrap().is_empty());
+
+        // a flat baseline leaves the two hits, and dips below it clamp to 0
+        let mut dark = vec![vec![3.0f32; 16]; 12];
+        dark[0][0] = 5.0;
+        let clean = subtract_background(&grid, &dark).unwrap();
+        assert_eq!((clean[5][2], clean[0][0], clean[0][1]), (57.0, 0.0, 0.0));
+        assert_eq!(extract_particles(&clean, &config).unwrap().len(), 2);
+
+        let err = subtract_background(&grid, &dark[1..]).unwrap_err();
+        assert_eq!(err.to_string(), "the reference is 16×11, the frame 16×12");
+    }
+
+    #[test]
+    fn test_calibration_shifts_type() {
+        // a 3×3 blob: a faint electron at gain 1, a bright alpha at gain 5
+        let mut grid = vec![vec![0.0f32; 8]; 8];
+        for row in &mut grid[2..5] {
+            row[2..5].fill(30.0);
+        }
+        let raw = ExtractConfig::default();
+        let strong = ExtractConfig {
+            calibration: Calibration {
+                gain: 5.0,
+                offset: 0.0,
+            },
+            ..raw
+        };
+
+        let types: Vec<PartType> = [raw, strong]
+            .iter()
+            .map(|config| {
+                let grid = prepare(grid.clone(), config);
+                let particles = extract_particles(&grid, config).unwrap();
+                assert_eq!(particles.len(), 1);
+                particles[0].particle_type(&grid)
+            })
+            .collect();
+        assert_ne!(types[0], PartType::ALPHA);
+        assert_eq!(types[1], PartType::ALPHA);
+
+        let shifted = calibrate(&grid, 2.0, 1.5);
+        assert_eq!((shifted[3][3], shifted[0][0]), (61.5, 0.0));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::classifier::Classifier;
+use crate::decoder::{PartType, Particle};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Frame-wide numbers for a quick sanity check of one frame
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameStats {
+    /// Sum of every cell in the frame
+    pub total_energy: f64,
+    /// Cells with a positive value
+    pub lit_pixels: usize,
+    /// All cells in the frame
+    pub pixels: usize,
+    pub counts: HashMap<PartType, usize>,
+    /// Cells at the saturation level, if [`FrameStats::with_saturation`] counted them
+    pub saturated_pixels: usize,
+}
+
+impl FrameStats {
+    pub fn compute(grid: &[Vec<f32>], particles: &[Particle], classifier: &dyn Classifier) -> Self {
+        let mut stats = FrameStats::default();
+        for &value in grid.iter().flatten() {
+            stats.total_energy += value as f64;
+            stats.pixels += 1;
+            if value > 0.0 {
+                stats.lit_pixels += 1;
+            }
+        }
+        for particle in particles {
+            *stats
+                .counts
+                .entry(classifier.classify(particle, grid))
+                .or_default() += 1;
+        }
+        stats
+    }
+
+    /// Also counts the cells of `grid` at or above `max_value` (see [`saturation_count`])
+    pub fn with_saturation(mut self, grid: &[Vec<f32>], max_value: f32) -> Self {
+        self.saturated_pixels = saturation_count(grid, max_value);
+        self
+    }
+
+    /// Fraction of the frame's cells that are lit; 0 for an empty frame
+    pub fn occupancy(&self) -> f64 {
+        if self.pixels == 0 {
+            0.0
+        } else {
+            self.lit_pixels as f64 / self.pixels as f64
+        }
+    }
+}
+
+/// Number of cells at or above `max_value`, the level the detector saturates at. Their
+/// energies are clipped, so a frame with many of them has unreliable energies.
+pub fn saturation_count(grid: &[Vec<f32>], max_value: f32) -> usize {
+    grid.iter().flatten().filter(|&&v| v >= max_value).count()
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ty in PartType::ALL {
+            writeln!(f, "{ty:?}: {}", self.counts.get(&ty).copied().unwrap_or(0))?;
+        }
+        writeln!(
+            f,
+            "lit pixels: {} of {} ({:.2}%)",
+            self.lit_pixels,
+            self.pixels,
+            100.0 * self.occupancy()
+        )?;
+        if self.saturated_pixels > 0 {
+            writeln!(f, "saturated pixels: {}", self.saturated_pixels)?;
+        }
+        write!(f, "total energy: {:.2}", self.total_energy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::HeuristicClassifier;
+
+    #[test]
+    fn test_compute() {
+        let mut grid = vec![vec![0.0f32; 10]; 10];
+        grid[1][1] = 5.0;
+        grid[1][2] = 7.5;
+        grid[8][8] = 2.5;
+        grid[5][5] = -1.0;
+        let particles = vec![
+            Particle::new(vec![(1, 1), (2, 1)]),
+            Particle::new(vec![(8, 8)]),
+        ];
+
+        let stats = FrameStats::compute(&grid, &particles, &HeuristicClassifier::default());
+
+        assert_eq!(stats.total_energy, 14.0);
+        assert_eq!(stats.lit_pixels, 3);
+        assert_eq!(stats.pixels, 100);
+        assert_eq!(stats.occupancy(), 0.03);
+        assert_eq!(stats.counts.get(&PartType::GAMMA), Some(&2));
+        assert_eq!(
+            stats.to_string(),
+            "ALPHA: 0\nBETA: 0\nGAMMA: 2\nMUON: 0\nUNKNOWN: 0\n\
+             lit pixels: 3 of 100 (3.00%)\ntotal energy: 14.00"
+        );
+        assert_eq!(FrameStats::default().occupancy(), 0.0);
+    }
+
+    #[test]
+    fn test_saturation_count() {
+        let mut grid = vec![vec![10.0f32; 10]; 10];
+        grid[0][0] = 4095.0;
+        grid[3][7] = 4095.0;
+        grid[9][2] = 5000.0;
+        grid[5][5] = 4094.0;
+        assert_eq!(saturation_count(&grid, 4095.0), 3);
+
+        let stats = FrameStats::compute(&grid, &[], &HeuristicClassifier::default())
+            .with_saturation(&grid, 4095.0);
+        assert_eq!(stats.saturated_pixels, 3);
+        assert!(stats.to_string().contains("\nsaturated pixels: 3\n"));
+    }
+}